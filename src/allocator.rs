@@ -0,0 +1,82 @@
+//! An `allocator_api2` allocator backed by a byte splitter, available with the `allocator-api2`
+//! feature.
+
+use allocator_api2::alloc::{AllocError, Allocator, Layout};
+use core::ptr::{self, NonNull};
+
+use SyncSplitter;
+
+/// Allocations are popped off the slice, aligned as requested. Deallocation does nothing: the
+/// memory is only reclaimed once the splitter is dropped (or `reset`).
+///
+/// Since `Allocator` is also implemented for references to allocators, a `&SyncSplitter<u8>` can be
+/// shared between threads and passed to collections like `allocator_api2::vec::Vec::new_in`.
+unsafe impl<'a> Allocator for SyncSplitter<'a, u8> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let data = if layout.size() == 0 {
+            // Zero-sized allocations don't use any of the slice, but must still be aligned.
+            layout.align() as *mut u8
+        } else {
+            let index = self.bump_aligned(layout.size(), layout.align()).ok_or(AllocError)?;
+            unsafe { self.data.add(index) }
+        };
+        let block = ptr::slice_from_raw_parts_mut(data, layout.size());
+        Ok(unsafe { NonNull::new_unchecked(block) })
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use allocator_api2::alloc::{Allocator, Layout};
+    use SyncSplitter;
+
+    #[test]
+    fn allocations_are_aligned_and_disjoint() {
+        let mut buffer = [0u8; 256];
+        let splitter = SyncSplitter::new(&mut buffer);
+        splitter.pop();
+
+        let mut blocks = Vec::new();
+        for &(size, align) in &[(3, 1), (8, 8), (1, 2), (16, 16), (0, 64), (5, 4)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let block = splitter.allocate(layout).unwrap();
+            assert_eq!(block.len(), size);
+            let start = block.as_ptr() as *mut u8 as usize;
+            assert_eq!(start % align, 0);
+            if size > 0 {
+                blocks.push((start, start + size));
+            }
+        }
+        blocks.sort();
+        for pair in blocks.windows(2) {
+            assert!(pair[0].1 <= pair[1].0);
+        }
+
+        let too_big = Layout::from_size_align(splitter.bytes_remaining() + 1, 1).unwrap();
+        assert!(splitter.allocate(too_big).is_err());
+    }
+
+    #[test]
+    fn backs_collections_from_many_threads() {
+        let mut buffer = vec![0u8; 1 << 16];
+        let splitter = SyncSplitter::new(&mut buffer);
+        ::std::thread::scope(|scope| {
+            for thread in 0..4u64 {
+                let splitter = &splitter;
+                scope.spawn(move || {
+                    let mut values = allocator_api2::vec::Vec::new_in(splitter);
+                    for value in 0..100 {
+                        values.push(thread * 1000 + value);
+                    }
+                    assert_eq!(values.len(), 100);
+                    assert!(values.iter().enumerate().all(|(index, &value)| {
+                        value == thread * 1000 + index as u64
+                    }));
+                });
+            }
+        });
+        assert!(splitter.done() > 4 * 100 * 8);
+    }
+}
@@ -0,0 +1,294 @@
+//! Indices branded with the splitter which popped them, so they can't be used with another arena.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+use core::slice;
+
+use SyncSplitter;
+
+/// An invariant lifetime, unique to each `SyncSplitter::with_branded` call.
+type Brand<'id> = PhantomData<fn(&'id ()) -> &'id ()>;
+
+/// The indices of the elements popped by a `pop_two`.
+type TwoIndices<'id> = (BrandedIndex<'id>, BrandedIndex<'id>);
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Creates a new splitter from a slice, whose pops return indices branded with this call, and
+    /// passes it to `build`.
+    ///
+    /// Every call gets a brand of its own, so the compiler rejects using an index with the arena of
+    /// another call, or outside of `build`. Once the splitter is `done`, the branded indices
+    /// index into the popped elements without any mix-up.
+    ///
+    /// Example
+    /// ===
+    /// ```rust,compile_fail
+    /// use sync_splitter::SyncSplitter;
+    ///
+    /// let (mut first, mut second) = ([0u32; 2], [0u32; 2]);
+    /// SyncSplitter::with_branded(&mut first, |first| {
+    ///     SyncSplitter::with_branded(&mut second, |second| {
+    ///         let (_, index) = first.pop().unwrap();
+    ///         // Error: `index` belongs to `first`.
+    ///         second.done()[index];
+    ///     })
+    /// });
+    /// ```
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `slice.len() > isize::MAX`.
+    pub fn with_branded<R, F>(slice: &'a mut [T], build: F) -> R
+    where
+        F: for<'id> FnOnce(BrandedSplitter<'id, 'a, T>) -> R,
+    {
+        build(BrandedSplitter {
+            splitter: SyncSplitter::new(slice),
+            brand: PhantomData,
+        })
+    }
+}
+
+/// A splitter whose pops return indices branded with its arena. See `SyncSplitter::with_branded`.
+pub struct BrandedSplitter<'id, 'a, T: 'a + Sync> {
+    splitter: SyncSplitter<'a, T>,
+    brand: Brand<'id>,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'id, 'a, T: 'a + Sync> BrandedSplitter<'id, 'a, T> {
+    /// Like `SyncSplitter::pop`, but returns a branded index.
+    #[inline]
+    pub fn pop(&self) -> Option<(&mut T, BrandedIndex<'id>)> {
+        self.splitter.pop().map(|(element, index)| (element, BrandedIndex::new(index)))
+    }
+
+    /// Like `SyncSplitter::pop_two`, but returns the branded index of each element.
+    #[inline]
+    pub fn pop_two(&self) -> Option<((&mut T, &mut T), TwoIndices<'id>)> {
+        self.splitter.pop_two().map(|(elements, index)| {
+            (elements, (BrandedIndex::new(index), BrandedIndex::new(index + 1)))
+        })
+    }
+
+    /// Like `SyncSplitter::pop_n`, but returns the branded range of the elements.
+    #[inline]
+    pub fn pop_n(&self, len: usize) -> Option<(&mut [T], BrandedRange<'id>)> {
+        self.splitter.pop_n(len).map(|(elements, start)| {
+            (
+                elements,
+                BrandedRange {
+                    start,
+                    len,
+                    brand: PhantomData,
+                },
+            )
+        })
+    }
+
+    /// Consumes the splitter and returns the popped elements, which can be indexed with the
+    /// branded indices.
+    pub fn done(self) -> BrandedArena<'id, 'a, T> {
+        let data = self.splitter.data;
+        let len = self.splitter.done();
+        BrandedArena {
+            elements: unsafe { slice::from_raw_parts_mut(data, len) },
+            brand: PhantomData,
+        }
+    }
+}
+
+/// The index of an element popped from a `BrandedSplitter`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BrandedIndex<'id> {
+    index: usize,
+    brand: Brand<'id>,
+}
+
+impl<'id> BrandedIndex<'id> {
+    #[inline]
+    fn new(index: usize) -> Self {
+        BrandedIndex {
+            index,
+            brand: PhantomData,
+        }
+    }
+
+    /// Returns the unbranded index.
+    #[inline]
+    pub fn get(self) -> usize {
+        self.index
+    }
+}
+
+impl<'id> fmt::Debug for BrandedIndex<'id> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_tuple("BrandedIndex").field(&self.index).finish()
+    }
+}
+
+/// The range of elements popped from a `BrandedSplitter` by a `pop_n`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BrandedRange<'id> {
+    start: usize,
+    len: usize,
+    brand: Brand<'id>,
+}
+
+impl<'id> BrandedRange<'id> {
+    /// Returns the number of elements in the range.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the range is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the branded index of the `position`-th element of the range, or `None` if
+    /// `position >= self.len()`.
+    #[inline]
+    pub fn get(&self, position: usize) -> Option<BrandedIndex<'id>> {
+        if position < self.len {
+            Some(BrandedIndex::new(self.start + position))
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the branded indices of the range's elements.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = BrandedIndex<'id>> {
+        (self.start..self.start + self.len).map(BrandedIndex::new)
+    }
+}
+
+impl<'id> fmt::Debug for BrandedRange<'id> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "BrandedRange({:?})", self.start..self.start + self.len)
+    }
+}
+
+/// The elements popped from a `BrandedSplitter`, indexed by its branded indices and ranges.
+pub struct BrandedArena<'id, 'a, T: 'a> {
+    elements: &'a mut [T],
+    brand: Brand<'id>,
+}
+
+impl<'id, 'a, T: 'a> BrandedArena<'id, 'a, T> {
+    /// Returns the popped elements.
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        self.elements
+    }
+
+    /// Returns the popped elements, mutably.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.elements
+    }
+
+    /// Consumes the arena and returns the popped elements, which can't be indexed with branded
+    /// indices anymore.
+    #[inline]
+    pub fn into_slice(self) -> &'a mut [T] {
+        self.elements
+    }
+}
+
+impl<'id, 'a, T: 'a> Index<BrandedIndex<'id>> for BrandedArena<'id, 'a, T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, index: BrandedIndex<'id>) -> &T {
+        &self.elements[index.index]
+    }
+}
+
+impl<'id, 'a, T: 'a> IndexMut<BrandedIndex<'id>> for BrandedArena<'id, 'a, T> {
+    #[inline]
+    fn index_mut(&mut self, index: BrandedIndex<'id>) -> &mut T {
+        &mut self.elements[index.index]
+    }
+}
+
+impl<'id, 'a, T: 'a> Index<BrandedRange<'id>> for BrandedArena<'id, 'a, T> {
+    type Output = [T];
+
+    #[inline]
+    fn index(&self, range: BrandedRange<'id>) -> &[T] {
+        &self.elements[range.start..range.start + range.len]
+    }
+}
+
+impl<'id, 'a, T: 'a> IndexMut<BrandedRange<'id>> for BrandedArena<'id, 'a, T> {
+    #[inline]
+    fn index_mut(&mut self, range: BrandedRange<'id>) -> &mut [T] {
+        &mut self.elements[range.start..range.start + range.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn branded_indices_index_the_done_arena() {
+        let mut buffer = [0u32; 8];
+        let values = SyncSplitter::with_branded(&mut buffer, |splitter| {
+            let (head, head_index) = splitter.pop().unwrap();
+            let ((first, second), (first_index, second_index)) = splitter.pop_two().unwrap();
+            let (rest, range) = splitter.pop_n(3).unwrap();
+            *head = 1;
+            *first = 2;
+            *second = 3;
+            rest.copy_from_slice(&[4, 5, 0]);
+            assert_eq!(range.len(), 3);
+            assert_eq!(range.get(3), None);
+            assert_eq!(format!("{:?}", range), "BrandedRange(3..6)");
+            assert_eq!(format!("{:?}", second_index), "BrandedIndex(2)");
+
+            let mut arena = splitter.done();
+            assert_eq!(arena.as_slice().len(), 6);
+            arena[range][2] = 6;
+            let order = [head_index, first_index, second_index];
+            order.iter().cloned().chain(range.iter()).map(|index| arena[index]).collect::<Vec<_>>()
+        });
+        assert_eq!(values, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(buffer, [1, 2, 3, 4, 5, 6, 0, 0]);
+    }
+
+    #[test]
+    fn branded_splitters_pop_from_threads() {
+        let mut buffer = [0usize; 100];
+        SyncSplitter::with_branded(&mut buffer, |splitter| {
+            let indices = ::std::thread::scope(|scope| {
+                let threads = (0..4)
+                    .map(|_| {
+                        let splitter = &splitter;
+                        scope.spawn(move || {
+                            let mut indices = Vec::new();
+                            while let Some((element, index)) = splitter.pop() {
+                                *element = index.get();
+                                indices.push(index);
+                            }
+                            indices
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                threads
+                    .into_iter()
+                    .flat_map(|thread| thread.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            let arena = splitter.done();
+            assert_eq!(indices.len(), 100);
+            assert!(indices.iter().all(|&index| arena[index] == index.get()));
+        });
+    }
+}
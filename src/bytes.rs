@@ -0,0 +1,242 @@
+//! Methods specific to splitters over byte slices.
+
+#[cfg(feature = "std")]
+use std::io::IoSliceMut;
+use core::fmt;
+use core::mem;
+use core::slice;
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a> SyncSplitter<'a, u8> {
+    /// Pops `size` bytes starting at an address aligned to `align` bytes, skipping (but not
+    /// returning) as many padding bytes as needed, and returns them.
+    ///
+    /// Also returns the bytes' offset into the original slice and the number of padding bytes
+    /// skipped before them. This is the building block for custom layouts which the typed pops
+    /// don't cover: the padding bytes are left untouched, but still count towards `done()`.
+    ///
+    /// Returns `None` if `align` isn't a power of two, or if not enough bytes were left in the
+    /// underlying slice.
+    #[inline]
+    pub fn pop_bytes(&self, size: usize, align: usize) -> Option<(&mut [u8], usize, usize)> {
+        if !align.is_power_of_two() {
+            return None;
+        }
+        self.bump_padded(size, align).map(|(index, padding)| {
+            (
+                unsafe { slice::from_raw_parts_mut(self.data.add(index), size) },
+                self.offset + index,
+                padding,
+            )
+        })
+    }
+
+    /// Pops consecutive regions of the given lengths in a single reservation and returns them as
+    /// `IoSliceMut`s, ready to be passed to vectored reads (`readv`, `recvmsg` etc.).
+    ///
+    /// Also returns the offset of each region into the original slice, in a separate vector so
+    /// the regions can be passed to vectored reads as they are.
+    ///
+    /// Returns `None` if not enough bytes were left in the underlying slice.
+    ///
+    /// Only available with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn pop_iovec(&self, lens: &[usize]) -> Option<(Vec<IoSliceMut<'_>>, Vec<usize>)> {
+        let total = lens.iter().try_fold(0usize, |total, &len| total.checked_add(len))?;
+        self.pop_n(total).map(|(mut rest, mut start)| {
+            lens.iter()
+                .map(move |&len| {
+                    let (region, remaining) = mem::take(&mut rest).split_at_mut(len);
+                    rest = remaining;
+                    start += len;
+                    (IoSliceMut::new(region), start - len)
+                })
+                .unzip()
+        })
+    }
+
+    /// Returns a `TextWriter`, which formats strings into this splitter's bytes.
+    ///
+    /// Each thread should use its own writer: a writer pops chunks of `chunk_len` bytes (or more,
+    /// for longer strings) at a time, so formatting doesn't touch the shared cursor on every
+    /// write.
+    #[inline]
+    pub fn text_writer(&self, chunk_len: usize) -> TextWriter<'_, 'a> {
+        TextWriter {
+            splitter: self,
+            chunk_len,
+            chunk: &mut [],
+            chunk_offset: 0,
+            start: 0,
+            end: 0,
+            failed: false,
+        }
+    }
+}
+
+/// Formats strings into the bytes of a `SyncSplitter<u8>`, with `write!` and friends, and hands
+/// them out as `(offset, len)` handles into the original slice.
+///
+/// Created by `SyncSplitter::text_writer`. Everything written since the last call to `finish`
+/// makes up one string, which is always contiguous: if it outgrows the current chunk, it's moved
+/// to a new one, and the rest of the old chunk is left unused (but still counts towards
+/// `done()`), as is the rest of the last chunk.
+pub struct TextWriter<'s, 'a: 's> {
+    splitter: &'s SyncSplitter<'a, u8>,
+    chunk_len: usize,
+    chunk: &'s mut [u8],
+    /// The offset of the chunk into the original slice.
+    chunk_offset: usize,
+    /// The range of the current string in the chunk.
+    start: usize,
+    end: usize,
+    /// Whether a write to the current string failed.
+    failed: bool,
+}
+
+impl<'s, 'a: 's> TextWriter<'s, 'a> {
+    /// Ends the current string and returns its offset into the original slice and its length,
+    /// in bytes. The next write starts a new string.
+    ///
+    /// Returns `None` if a write failed since the last call, because the splitter ran out of
+    /// bytes.
+    pub fn finish(&mut self) -> Option<(usize, usize)> {
+        let string = (self.chunk_offset + self.start, self.end - self.start);
+        self.start = self.end;
+        if mem::replace(&mut self.failed, false) {
+            None
+        } else {
+            Some(string)
+        }
+    }
+}
+
+impl<'s, 'a: 's> fmt::Write for TextWriter<'s, 'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        if self.failed {
+            return Err(fmt::Error);
+        }
+        if string.len() > self.chunk.len() - self.end {
+            let len = self.end - self.start + string.len();
+            let (chunk, offset) = match self.splitter.pop_n(len.max(self.chunk_len)) {
+                Some(popped) => popped,
+                None => {
+                    self.failed = true;
+                    return Err(fmt::Error);
+                }
+            };
+            chunk[..self.end - self.start].copy_from_slice(&self.chunk[self.start..self.end]);
+            self.chunk = chunk;
+            self.chunk_offset = offset;
+            self.end -= self.start;
+            self.start = 0;
+        }
+        self.chunk[self.end..self.end + string.len()].copy_from_slice(string.as_bytes());
+        self.end += string.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+    use core::str;
+    use SyncSplitter;
+
+    #[repr(align(64))]
+    struct Aligned([u8; 128]);
+
+    #[test]
+    fn pop_bytes_pads_to_alignment() {
+        let mut buffer = Aligned([0; 128]);
+        let splitter = SyncSplitter::new(&mut buffer.0[1..]);
+        let (bytes, offset, padding) = splitter.pop_bytes(3, 1).unwrap();
+        assert_eq!((bytes.len(), offset, padding), (3, 0, 0));
+
+        let (bytes, offset, padding) = splitter.pop_bytes(8, 16).unwrap();
+        assert_eq!((offset, padding), (15, 12));
+        assert_eq!(bytes.as_ptr() as usize % 16, 0);
+        assert!(splitter.pop_bytes(1, 3).is_none());
+        assert!(splitter.pop_bytes(80, 64).is_none());
+        let (_, offset, padding) = splitter.pop_bytes(0, 8).unwrap();
+        assert_eq!((offset, padding), (23, 0));
+        assert_eq!(splitter.done(), 23);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pop_iovec_returns_consecutive_regions() {
+        use std::io::Read;
+
+        let mut buffer = [0u8; 10];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop();
+            {
+                let (mut iovecs, offsets) = splitter.pop_iovec(&[2, 0, 3]).unwrap();
+                assert_eq!(offsets, [1, 3, 3]);
+                assert_eq!(iovecs.iter().map(|iovec| iovec.len()).collect::<Vec<_>>(), [2, 0, 3]);
+
+                let mut source = &[1u8, 2, 3, 4, 5, 6][..];
+                assert_eq!(source.read_vectored(&mut iovecs).unwrap(), 5);
+            }
+            assert!(splitter.pop_iovec(&[5]).is_none());
+            assert!(splitter.pop_iovec(&[usize::MAX, 2]).is_none());
+            assert_eq!(splitter.pop_iovec(&[4]).map(|(_, offsets)| offsets), Some(vec![6]));
+            assert_eq!(splitter.done(), 10);
+        }
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn text_writers_hand_out_contiguous_strings() {
+        let mut buffer = [0u8; 8192];
+        let strings = {
+            let splitter = SyncSplitter::new(&mut buffer);
+            let strings = ::std::thread::scope(|scope| {
+                let threads = (0..4)
+                    .map(|thread| {
+                        let splitter = &splitter;
+                        scope.spawn(move || {
+                            let mut writer = splitter.text_writer(64);
+                            (0..50)
+                                .map(|line| {
+                                    write!(writer, "thread {}", thread).unwrap();
+                                    writeln!(writer, ", line {}", line).unwrap();
+                                    (thread, line, writer.finish().unwrap())
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                threads
+                    .into_iter()
+                    .flat_map(|thread| thread.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            assert!(splitter.done() <= 8192);
+            strings
+        };
+        for (thread, line, (offset, len)) in strings {
+            let string = str::from_utf8(&buffer[offset..offset + len]).unwrap();
+            assert_eq!(string, format!("thread {}, line {}\n", thread, line));
+        }
+    }
+
+    #[test]
+    fn text_writers_fail_when_exhausted() {
+        let mut buffer = [0u8; 12];
+        let splitter = SyncSplitter::new(&mut buffer);
+        let mut writer = splitter.text_writer(4);
+        writer.write_str("abc").unwrap();
+        writer.write_str("de").unwrap();
+        assert_eq!(writer.finish(), Some((4, 5)));
+        assert_eq!(writer.finish(), Some((9, 0)));
+        assert!(writer.write_str("f").is_err());
+        assert!(writer.write_str("").is_err());
+        assert_eq!(writer.finish(), None);
+        assert_eq!(splitter.done(), 9);
+    }
+}
@@ -0,0 +1,174 @@
+//! Arena sizes for regular trees, computed (and checked for overflow) at compile time.
+
+/// Returns the number of nodes in a complete binary tree with `depth` levels, `2^depth - 1`.
+///
+/// Panics
+/// ===
+///
+/// If the result overflows a `usize`. In a constant, this is a compile-time error instead: see
+/// `arena_size_for_binary_depth!`.
+pub const fn binary_tree_len(depth: u32) -> usize {
+    kary_tree_len(2, depth)
+}
+
+/// Returns the number of nodes in a complete tree with `depth` levels, where every internal node
+/// has `arity` children: `(arity^depth - 1) / (arity - 1)`, or just `depth` for an `arity` of one.
+///
+/// Panics
+/// ===
+///
+/// If the result overflows a `usize`, or if `arity` is zero (and `depth > 1`).
+pub const fn kary_tree_len(arity: usize, depth: u32) -> usize {
+    let mut len = 0usize;
+    let mut level = 1usize;
+    let mut remaining = depth;
+    while remaining > 0 {
+        len = match len.checked_add(level) {
+            Some(len) => len,
+            None => panic!("tree size overflows usize"),
+        };
+        remaining -= 1;
+        if remaining > 0 {
+            level = match level.checked_mul(arity) {
+                Some(level) if level > 0 => level,
+                Some(_) => panic!("trees with more than one level need a non-zero arity"),
+                None => panic!("tree size overflows usize"),
+            };
+        }
+    }
+    len
+}
+
+/// Returns the number of nodes in a binary BVH (or any full binary tree) with `leaves` leaves,
+/// `2 * leaves - 1`.
+///
+/// This is also the largest number of nodes in any BVH with `leaves` leaves whose internal nodes
+/// have at least two children, whatever their arity, so it's the safe size for arenas of BVHs
+/// whose nodes aren't always full.
+///
+/// Panics
+/// ===
+///
+/// If the result overflows a `usize`.
+pub const fn bvh_len(leaves: usize) -> usize {
+    if leaves == 0 {
+        return 0;
+    }
+    match leaves.checked_mul(2) {
+        Some(len) => len - 1,
+        None => panic!("tree size overflows usize"),
+    }
+}
+
+/// Returns the number of nodes in a full BVH with `leaves` leaves, where every internal node
+/// (except maybe one) has exactly `arity` children.
+///
+/// This is the smallest number of nodes in a BVH whose internal nodes have at most `arity`
+/// children, so only use it to size arenas for BVHs which are built full. Others need up to
+/// `bvh_len(leaves)` nodes.
+///
+/// Panics
+/// ===
+///
+/// If the result overflows a `usize`, or if `arity < 2` (and `leaves > 1`).
+pub const fn full_kary_bvh_len(arity: usize, leaves: usize) -> usize {
+    if leaves <= 1 {
+        return leaves;
+    }
+    if arity < 2 {
+        panic!("BVHs with more than one leaf need an arity of at least two");
+    }
+    // Every internal node turns `arity` nodes into one, and internal nodes take at most `arity`
+    // nodes each.
+    let internal = (leaves - 1).div_ceil(arity - 1);
+    match leaves.checked_add(internal) {
+        Some(len) => len,
+        None => panic!("tree size overflows usize"),
+    }
+}
+
+/// Evaluates to the number of nodes in a complete binary tree with the given (constant) number of
+/// levels, failing to compile if that overflows a `usize`. See `binary_tree_len`.
+///
+/// Example
+/// ===
+/// ```rust
+/// #[macro_use]
+/// extern crate sync_splitter;
+///
+/// # fn main() {
+/// let mut arena = [0u32; arena_size_for_binary_depth!(5)];
+/// assert_eq!(arena.len(), 31);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! arena_size_for_binary_depth {
+    ($depth:expr) => {
+        const { $crate::binary_tree_len($depth) }
+    };
+}
+
+/// Like `arena_size_for_binary_depth!`, for trees whose internal nodes have the given (constant)
+/// number of children. See `kary_tree_len`.
+#[macro_export]
+macro_rules! arena_size_for_kary_depth {
+    ($arity:expr, $depth:expr) => {
+        const { $crate::kary_tree_len($arity, $depth) }
+    };
+}
+
+/// Like `arena_size_for_binary_depth!`, for BVHs with the given (constant) number of leaves, of
+/// any arity. See `bvh_len`.
+#[macro_export]
+macro_rules! arena_size_for_bvh_leaves {
+    ($leaves:expr) => {
+        const { $crate::bvh_len($leaves) }
+    };
+}
+
+/// Like `arena_size_for_binary_depth!`, for full BVHs with the given (constant) arity and number of
+/// leaves. See `full_kary_bvh_len`.
+#[macro_export]
+macro_rules! arena_size_for_full_kary_bvh {
+    ($arity:expr, $leaves:expr) => {
+        const { $crate::full_kary_bvh_len($arity, $leaves) }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{binary_tree_len, bvh_len, full_kary_bvh_len, kary_tree_len};
+
+    #[test]
+    fn tree_lens_match_counted_nodes() {
+        assert_eq!([0, 1, 2, 3, 10].map(binary_tree_len), [0, 1, 3, 7, 1023]);
+        assert_eq!(binary_tree_len(usize::BITS), usize::MAX);
+        assert_eq!(kary_tree_len(4, 3), 1 + 4 + 16);
+        assert_eq!(kary_tree_len(1, 5), 5);
+        assert_eq!(kary_tree_len(0, 1), 1);
+        assert_eq!(arena_size_for_kary_depth!(3, 4), 40);
+
+        assert_eq!([0, 1, 2, 5].map(bvh_len), [0, 1, 3, 9]);
+        assert_eq!(arena_size_for_bvh_leaves!(8), 15);
+        // A 4-ary BVH of 16 leaves built as a binary tree has 31 nodes.
+        assert_eq!(arena_size_for_bvh_leaves!(16), 31);
+
+        // Four leaves under one node, then that node and three more leaves under the root.
+        assert_eq!(full_kary_bvh_len(4, 7), 9);
+        assert_eq!(full_kary_bvh_len(4, 5), 7);
+        assert_eq!(full_kary_bvh_len(2, 5), bvh_len(5));
+        assert_eq!(arena_size_for_full_kary_bvh!(4, 16), 21);
+    }
+
+    #[test]
+    #[should_panic]
+    fn tree_lens_panic_on_overflow() {
+        binary_tree_len(usize::BITS + 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bvh_lens_panic_on_overflow() {
+        bvh_len(usize::MAX / 2 + 1);
+    }
+}
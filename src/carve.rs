@@ -0,0 +1,234 @@
+//! Partitioning one byte buffer into several typed splitters.
+
+use alloc::vec::Vec;
+use core::mem;
+use core::slice;
+
+use SyncSplitter;
+
+/// Partitions a byte buffer into consecutive, typed `SyncSplitter` regions.
+///
+/// This keeps differently typed arenas (e.g. one per kind of AST node) in a single allocation,
+/// while each region still has its own type and its own indices. Use `Carver::done` to get a
+/// combined report once the regions' splitters are done with.
+///
+/// Example
+/// ===
+/// ```rust
+/// use sync_splitter::Carver;
+///
+/// let mut buffer = vec![0u8; 1024];
+/// let mut carver = Carver::new(&mut buffer);
+/// let exprs = carver.carve::<[u32; 2]>(32).unwrap();
+/// let names = carver.carve::<u8>(100).unwrap();
+/// exprs.pop_two();
+/// names.pop_n(5);
+/// let report = carver.done(&[&exprs, &names]);
+/// assert_eq!(report.popped(), [2, 5]);
+/// assert_eq!(report.bytes_used(), 2 * 8 + 5);
+/// ```
+///
+/// Only `Plain` types can be carved, since the buffer is readable as bytes again afterwards, so
+/// this doesn't compile:
+///
+/// ```rust,compile_fail
+/// use sync_splitter::Carver;
+///
+/// let mut buffer = vec![0u8; 1024];
+/// // `(u8, u32)` has three bytes of padding, which would be uninitialized bytes of `buffer`.
+/// Carver::new(&mut buffer).carve::<(u8, u32)>(4);
+/// ```
+pub struct Carver<'a> {
+    rest: &'a mut [u8],
+    bytes_carved: usize,
+}
+
+impl<'a> Carver<'a> {
+    /// Creates a carver which partitions `buffer`.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Carver {
+            rest: buffer,
+            bytes_carved: 0,
+        }
+    }
+
+    /// Returns the number of bytes carved so far, including padding for alignment.
+    #[inline]
+    pub fn bytes_carved(&self) -> usize {
+        self.bytes_carved
+    }
+
+    /// Returns the number of bytes which are still available to be carved.
+    #[inline]
+    pub fn bytes_remaining(&self) -> usize {
+        self.rest.len()
+    }
+
+    /// Carves the next `len` elements of type `T`, initialized to `T::default()`, and returns a
+    /// splitter over them.
+    ///
+    /// Returns `None` if not enough bytes were left in the buffer.
+    pub fn carve<T: Plain + Default>(&mut self, len: usize) -> Option<SyncSplitter<'a, T>> {
+        self.carve_with(len, T::default)
+    }
+
+    /// Like `carve`, but initializes the elements with `init`.
+    pub fn carve_with<T, F>(&mut self, len: usize, mut init: F) -> Option<SyncSplitter<'a, T>>
+    where
+        T: Plain,
+        F: FnMut() -> T,
+    {
+        let padding = self.rest.as_ptr().align_offset(mem::align_of::<T>());
+        let bytes = len.checked_mul(mem::size_of::<T>())?.checked_add(padding)?;
+        if bytes > self.rest.len() {
+            return None;
+        }
+        let (region, rest) = mem::take(&mut self.rest).split_at_mut(bytes);
+        self.rest = rest;
+        self.bytes_carved += bytes;
+
+        let data = unsafe { region.as_mut_ptr().add(padding) as *mut T };
+        for index in 0..len {
+            // The region is aligned for `T` and large enough for `len` of them.
+            unsafe { data.add(index).write(init()) };
+        }
+        // `T: Plain`, so every byte of the region is still initialized when it's read as bytes.
+        Some(SyncSplitter::new(unsafe { slice::from_raw_parts_mut(data, len) }))
+    }
+
+    /// Reports on every region carved from this buffer, given their splitters.
+    pub fn done(self, regions: &[&dyn Region]) -> CarveReport {
+        CarveReport {
+            popped: regions.iter().map(|region| region.popped()).collect(),
+            bytes_used: regions.iter().map(|region| region.bytes_used()).sum(),
+            bytes_carved: self.bytes_carved,
+        }
+    }
+}
+
+/// Element types which can be carved from a byte buffer with a `Carver`.
+///
+/// Safety
+/// ===
+///
+/// Implementors must be `Copy` and must not contain any padding bytes: once the buffer isn't
+/// borrowed anymore, every byte of a carved value can be read as a `u8`.
+pub unsafe trait Plain: Copy + Sync {}
+
+macro_rules! impl_plain {
+    ($($type:ty),*) => {
+        $(unsafe impl Plain for $type {})*
+    };
+}
+
+impl_plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// The splitters of regions carved with a `Carver`, whatever their element types.
+pub trait Region {
+    /// Returns the number of elements popped so far.
+    fn popped(&self) -> usize;
+
+    /// Returns the number of bytes popped so far.
+    fn bytes_used(&self) -> usize;
+}
+
+impl<'a, T: 'a + Sync> Region for SyncSplitter<'a, T> {
+    fn popped(&self) -> usize {
+        self.next.load()
+    }
+
+    fn bytes_used(&self) -> usize {
+        SyncSplitter::bytes_used(self)
+    }
+}
+
+/// The combined report returned by `Carver::done`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CarveReport {
+    popped: Vec<usize>,
+    bytes_used: usize,
+    bytes_carved: usize,
+}
+
+impl CarveReport {
+    /// Returns the number of popped elements of each region, in the order they were passed in.
+    #[inline]
+    pub fn popped(&self) -> &[usize] {
+        &self.popped
+    }
+
+    /// Returns the total number of bytes popped across all regions.
+    #[inline]
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used
+    }
+
+    /// Returns the total number of bytes carved, including padding for alignment.
+    #[inline]
+    pub fn bytes_carved(&self) -> usize {
+        self.bytes_carved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::{Carver, Plain};
+
+    /// A struct with its padding made explicit, so it can be carved.
+    #[derive(Clone, Copy, Default, Debug, PartialEq)]
+    #[repr(C)]
+    struct Padded {
+        tag: u8,
+        padding: [u8; 3],
+        value: u32,
+    }
+
+    unsafe impl Plain for Padded {}
+
+    #[test]
+    fn carved_regions_are_aligned_and_disjoint() {
+        let mut buffer = vec![0u8; 256];
+        let mut carver = Carver::new(&mut buffer[1..]);
+        let bytes = carver.carve_with(3, || 7u8).unwrap();
+        let words = carver.carve::<u64>(4).unwrap();
+        assert!(carver.carve::<u64>(100).is_none());
+        let halves = carver.carve::<u16>(2).unwrap();
+        assert_eq!(bytes.pop_n(3), Some((&mut [7u8, 7, 7][..], 0)));
+
+        let (word, index) = words.pop().unwrap();
+        assert_eq!((*word, index), (0, 0));
+        assert_eq!(word as *mut u64 as usize % mem::align_of::<u64>(), 0);
+        *word = u64::MAX;
+        assert_eq!(halves.pop_two(), Some(((&mut 0, &mut 0), 0)));
+
+        assert!(carver.bytes_carved() >= 3 + 4 * 8 + 2 * 2);
+        assert_eq!(carver.bytes_carved() + carver.bytes_remaining(), 255);
+        let report = carver.done(&[&bytes, &words, &halves]);
+        assert_eq!(report.popped(), [3, 1, 2]);
+        assert_eq!(report.bytes_used(), 3 + 8 + 4);
+    }
+
+    #[test]
+    fn carved_bytes_stay_initialized() {
+        let mut buffer = vec![0xffu8; 64];
+        {
+            let mut carver = Carver::new(&mut buffer);
+            let padded = carver.carve::<Padded>(4).unwrap();
+            let (element, _) = padded.pop().unwrap();
+            *element = Padded {
+                tag: 1,
+                padding: [0; 3],
+                value: 2,
+            };
+            assert_eq!(padded.done(), 1);
+        }
+        // Every byte of the buffer can be read back, padding included.
+        let carved = buffer.iter().position(|&byte| byte != 0xff).unwrap();
+        assert_eq!(buffer[carved..carved + 4], [1, 0, 0, 0]);
+        assert_eq!(buffer[carved + 4..carved + 8], 2u32.to_ne_bytes());
+        assert!(buffer[carved + 8..carved + 32].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,258 @@
+//! Splitting a runtime-defined set of columns (struct-of-arrays storage) with a single cursor.
+
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::marker::PhantomData;
+use core::mem;
+use core::slice;
+
+use cursor::Cursor;
+
+const MAX_COLUMNS: usize = 64;
+
+/// Identifies a column registered with a `ColumnSplitter`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ColumnId(usize);
+
+struct Column {
+    data: *mut u8,
+    element_size: usize,
+    type_id: Option<TypeId>,
+}
+
+/// A `ColumnSplitter` allows multiple threads to claim rows of a set of columns at the same time,
+/// where popping a row claims the slot with the same index in every column.
+///
+/// Unlike a struct of typed splitters, the set of columns is registered at runtime and their types
+/// are erased, which suits ECS-style storage with dynamic archetypes.
+pub struct ColumnSplitter<'a> {
+    columns: Vec<Column>,
+    len: usize,
+    next: Cursor,
+    dummy: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> ColumnSplitter<'a> {
+    /// Creates a new `ColumnSplitter` with no columns.
+    ///
+    /// Until a column is registered, the number of available rows is unbounded.
+    pub fn new() -> Self {
+        ColumnSplitter {
+            columns: Vec::new(),
+            len: isize::MAX as usize,
+            next: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Registers a typed column and returns its id.
+    ///
+    /// The number of rows available is the length of the shortest registered column.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If 64 columns were already registered.
+    pub fn add_column<T: Send + 'static>(&mut self, column: &'a mut [T]) -> ColumnId {
+        let len = column.len();
+        unsafe {
+            self.add_column_with_type(
+                column.as_mut_ptr() as *mut u8,
+                len,
+                mem::size_of::<T>(),
+                Some(TypeId::of::<T>()),
+            )
+        }
+    }
+
+    /// Registers a type-erased column of `len` elements of `element_size` bytes each, starting at
+    /// `data`, and returns its id.
+    ///
+    /// Rows of such columns can only be accessed through `ColumnRows::take_raw`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If 64 columns were already registered.
+    ///
+    /// Safety
+    /// ===
+    ///
+    /// `data` must be valid for reads and writes of `len * element_size` bytes for the lifetime
+    /// `'a`, and that memory must not be accessed through any other pointer during that time. It
+    /// must also be safe to send its elements to other threads.
+    pub unsafe fn add_column_raw(
+        &mut self,
+        data: *mut u8,
+        len: usize,
+        element_size: usize,
+    ) -> ColumnId {
+        self.add_column_with_type(data, len, element_size, None)
+    }
+
+    unsafe fn add_column_with_type(
+        &mut self,
+        data: *mut u8,
+        len: usize,
+        element_size: usize,
+        type_id: Option<TypeId>,
+    ) -> ColumnId {
+        assert!(self.columns.len() < MAX_COLUMNS, "at most {} columns", MAX_COLUMNS);
+        self.len = self.len.min(len);
+        self.columns.push(Column {
+            data,
+            element_size,
+            type_id,
+        });
+        ColumnId(self.columns.len() - 1)
+    }
+
+    /// Returns the number of registered columns.
+    #[inline]
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Pops `len` consecutive rows and returns them.
+    ///
+    /// Also returns the index of the first popped row.
+    ///
+    /// Returns `None` if not enough rows were left in the shortest column.
+    #[inline]
+    pub fn pop_rows(&self, len: usize) -> Option<(ColumnRows<'_>, usize)> {
+        self.next.bump(self.len, len).map(|index| {
+            (
+                ColumnRows {
+                    columns: &self.columns,
+                    start: index,
+                    len,
+                    taken: 0,
+                },
+                index,
+            )
+        })
+    }
+
+    /// Pops a single row and returns it.
+    ///
+    /// Also returns the index of the popped row.
+    ///
+    /// Returns `None` if the shortest column was exhausted.
+    #[inline]
+    pub fn pop_row(&self) -> Option<(ColumnRows<'_>, usize)> {
+        self.pop_rows(1)
+    }
+
+    /// Consumes the splitter and returns the total number of popped rows.
+    #[inline]
+    pub fn done(self) -> usize {
+        self.next.load()
+    }
+}
+
+impl<'a> Default for ColumnSplitter<'a> {
+    fn default() -> Self {
+        ColumnSplitter::new()
+    }
+}
+
+unsafe impl<'a> Sync for ColumnSplitter<'a> {}
+unsafe impl<'a> Send for ColumnSplitter<'a> {}
+
+/// A range of rows popped from a `ColumnSplitter`.
+///
+/// Each column's part of the range can be taken (at most once) with `take` or `take_raw`.
+pub struct ColumnRows<'s> {
+    columns: &'s [Column],
+    start: usize,
+    len: usize,
+    taken: u64,
+}
+
+impl<'s> ColumnRows<'s> {
+    /// Returns the number of popped rows.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no rows were popped.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Takes the popped part of a typed column.
+    ///
+    /// Returns `None` if the column was already taken, doesn't exist, or was registered with a
+    /// different type (or with `add_column_raw`).
+    pub fn take<T: 'static>(&mut self, column: ColumnId) -> Option<&'s mut [T]> {
+        if self.columns.get(column.0)?.type_id != Some(TypeId::of::<T>()) {
+            return None;
+        }
+        self.take_raw(column)
+            .map(|data| unsafe { slice::from_raw_parts_mut(data as *mut T, self.len) })
+    }
+
+    /// Takes the popped part of any column, returning a pointer to its first element.
+    ///
+    /// The pointer is valid for reads and writes of `self.len()` elements of the column's element
+    /// size, for as long as the splitter is borrowed.
+    ///
+    /// Returns `None` if the column was already taken or doesn't exist.
+    pub fn take_raw(&mut self, column: ColumnId) -> Option<*mut u8> {
+        let bit = 1 << column.0;
+        let column = self.columns.get(column.0)?;
+        if self.taken & bit != 0 {
+            return None;
+        }
+        self.taken |= bit;
+        Some(unsafe { column.data.add(self.start * column.element_size) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColumnSplitter;
+    use std::string::String;
+
+    #[test]
+    fn pops_rows_across_all_columns() {
+        let mut positions = [0u32; 4];
+        let mut names = vec![String::new(); 3];
+        let mut raw = [0u16; 5];
+        {
+            let mut splitter = ColumnSplitter::new();
+            let position_column = splitter.add_column(&mut positions);
+            let name_column = splitter.add_column(&mut names);
+            let raw_column = unsafe { splitter.add_column_raw(raw.as_mut_ptr() as *mut u8, 5, 2) };
+            assert_eq!(splitter.num_columns(), 3);
+
+            {
+                let (mut rows, index) = splitter.pop_rows(2).unwrap();
+                assert_eq!((rows.len(), index), (2, 0));
+                assert!(rows.take::<u64>(position_column).is_none());
+                assert!(rows.take::<u16>(raw_column).is_none());
+
+                let positions = rows.take::<u32>(position_column).unwrap();
+                let names = rows.take::<String>(name_column).unwrap();
+                assert!(rows.take::<u32>(position_column).is_none());
+                positions.copy_from_slice(&[1, 2]);
+                names[0].push('a');
+                names[1].push('b');
+
+                let (mut row, index) = splitter.pop_row().unwrap();
+                assert_eq!(index, 2);
+                row.take::<u32>(position_column).unwrap()[0] = 3;
+                unsafe { *(row.take_raw(raw_column).unwrap() as *mut u16) = 7 };
+                assert!(row.take_raw(raw_column).is_none());
+
+                assert!(splitter.pop_row().is_none());
+            }
+            assert_eq!(splitter.done(), 3);
+        }
+        assert_eq!(positions, [1, 2, 3, 0]);
+        assert_eq!(names, ["a", "b", ""]);
+        assert_eq!(raw, [0, 0, 7, 0, 0]);
+    }
+}
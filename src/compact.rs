@@ -0,0 +1,107 @@
+//! Squeezing the live elements of an arena into a dense prefix.
+
+use alloc::vec::Vec;
+
+/// Moves the elements of `arena` for which `is_live` returns `true` to a dense prefix (keeping
+/// their relative order) and returns the table mapping old indices to new ones.
+///
+/// Holes are left behind in an arena when some popped elements are no longer needed, or when
+/// reserved ranges (see `SyncSplitter::pop_splitter`) aren't fully used. After compacting, the
+/// arena can be truncated to `RemapTable::live_len` and the indices stored in its elements
+/// rewritten with `RemapTable::rewrite_indices`.
+pub fn compact<T, F>(arena: &mut [T], mut is_live: F) -> RemapTable
+where
+    F: FnMut(&T) -> bool,
+{
+    let mut new_indices = Vec::with_capacity(arena.len());
+    let mut live_len = 0;
+    for index in 0..arena.len() {
+        if is_live(&arena[index]) {
+            arena.swap(live_len, index);
+            new_indices.push(live_len);
+            live_len += 1;
+        } else {
+            new_indices.push(DEAD);
+        }
+    }
+    RemapTable {
+        new_indices,
+        live_len,
+    }
+}
+
+const DEAD: usize = usize::MAX;
+
+/// Maps the indices of an arena before `compact` to indices after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemapTable {
+    new_indices: Vec<usize>,
+    live_len: usize,
+}
+
+impl RemapTable {
+    /// Returns the new index of the element which was at `old_index`, or `None` if it wasn't live
+    /// (or `old_index` was out of bounds).
+    #[inline]
+    pub fn get(&self, old_index: usize) -> Option<usize> {
+        match self.new_indices.get(old_index) {
+            Some(&DEAD) | None => None,
+            Some(&new_index) => Some(new_index),
+        }
+    }
+
+    /// Returns the number of live elements, which now make up the arena's prefix.
+    #[inline]
+    pub fn live_len(&self) -> usize {
+        self.live_len
+    }
+
+    /// Returns the number of elements in the arena before compacting.
+    #[inline]
+    pub fn old_len(&self) -> usize {
+        self.new_indices.len()
+    }
+
+    /// Calls `rewrite` on every live element of the compacted `arena`, so it can replace the old
+    /// indices it stores (typically with `RemapTable::get`).
+    pub fn rewrite_indices<T, F>(&self, arena: &mut [T], mut rewrite: F)
+    where
+        F: FnMut(&mut T, &RemapTable),
+    {
+        for element in &mut arena[..self.live_len] {
+            rewrite(element, self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compact;
+
+    #[test]
+    fn compacts_and_remaps() {
+        // (live, next element in a linked list).
+        let mut arena = [
+            (true, Some(2)),
+            (false, None),
+            (true, Some(4)),
+            (false, None),
+            (true, None),
+        ];
+        let remap = compact(&mut arena, |&(live, _)| live);
+        assert_eq!((remap.live_len(), remap.old_len()), (3, 5));
+        assert_eq!(
+            (0..6).map(|index| remap.get(index)).collect::<Vec<_>>(),
+            [Some(0), None, Some(1), None, Some(2), None]
+        );
+
+        remap.rewrite_indices(&mut arena, |&mut (_, ref mut next), remap| {
+            *next = next.and_then(|next| remap.get(next));
+        });
+        assert_eq!(
+            arena[..remap.live_len()],
+            [(true, Some(1)), (true, Some(2)), (true, None)]
+        );
+        assert!(arena[remap.live_len()..].iter().all(|&(live, _)| !live));
+    }
+}
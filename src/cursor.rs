@@ -0,0 +1,192 @@
+//! The shared position behind every splitter.
+//!
+//! By default this is an `AtomicUsize` updated with compare-and-swap loops. With the
+//! `critical-section` feature it's a plain integer updated inside a critical section instead, for
+//! targets without atomic compare-and-swap (where a critical section usually just disables
+//! interrupts on a single core).
+
+#[cfg(not(feature = "critical-section"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use TryPopError;
+
+#[cfg(feature = "critical-section")]
+use core::cell::Cell;
+#[cfg(feature = "critical-section")]
+use critical_section::{self, CriticalSection, Mutex, RestoreState};
+#[cfg(feature = "critical-section")]
+use no_unwind;
+
+pub struct Cursor {
+    #[cfg(not(feature = "critical-section"))]
+    value: AtomicUsize,
+    #[cfg(feature = "critical-section")]
+    value: Mutex<Cell<usize>>,
+}
+
+impl Cursor {
+    #[inline]
+    pub const fn new(value: usize) -> Self {
+        Cursor {
+            #[cfg(not(feature = "critical-section"))]
+            value: AtomicUsize::new(value),
+            #[cfg(feature = "critical-section")]
+            value: Mutex::new(Cell::new(value)),
+        }
+    }
+
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    pub fn load(&self) -> usize {
+        self.value.load(Ordering::Acquire)
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    pub fn load(&self) -> usize {
+        critical_section::with(|section| self.value.borrow(section).get())
+    }
+
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut usize {
+        self.value.get_mut()
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut usize {
+        self.value.get_mut().get_mut()
+    }
+
+    /// Atomically replaces the current value `current` with `new`, where `update(current)` returns
+    /// `Some((result, new))`, and returns `result`. Returns `None`, leaving the value unchanged, if
+    /// `update` does.
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    pub fn update<R, F>(&self, mut update: F) -> Option<R>
+    where
+        F: FnMut(usize) -> Option<(R, usize)>,
+    {
+        loop {
+            let current = self.value.load(Ordering::Acquire);
+            let (result, new) = update(current)?;
+            if self.value
+                .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return Some(result);
+            }
+        }
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    pub fn update<R, F>(&self, mut update: F) -> Option<R>
+    where
+        F: FnMut(usize) -> Option<(R, usize)>,
+    {
+        // Like `critical_section::with`, but the implementation may panic (e.g. when entered
+        // reentrantly), so only its calls go through `no_unwind`; the update stays inline.
+        let _section = Section(no_unwind(|| unsafe { critical_section::acquire() }));
+        let value = self.value.borrow(unsafe { CriticalSection::new() });
+        let (result, new) = update(value.get())?;
+        value.set(new);
+        Some(result)
+    }
+
+    /// Advances the value by `len`, as long as that doesn't take it past `limit`. Returns the value
+    /// before the increment.
+    #[inline]
+    pub fn bump(&self, limit: usize, len: usize) -> Option<usize> {
+        self.update(|index| {
+            if len <= limit && index <= limit - len {
+                Some((index, index + len))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Like `bump`, but makes a single attempt, which may fail spuriously or because another thread
+    /// changed the value concurrently.
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    pub fn bump_weak(&self, limit: usize, len: usize) -> Result<usize, TryPopError> {
+        let index = self.value.load(Ordering::Acquire);
+        if len > limit || index > limit - len {
+            return Err(TryPopError::Exhausted);
+        }
+        self.value
+            .compare_exchange_weak(index, index + len, Ordering::AcqRel, Ordering::Acquire)
+            .map_err(|_| TryPopError::Contended)
+    }
+
+    /// Like `bump`, but makes a single attempt, which may fail spuriously or because another thread
+    /// changed the value concurrently.
+    ///
+    /// Inside a critical section, the attempt can't be contended.
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    pub fn bump_weak(&self, limit: usize, len: usize) -> Result<usize, TryPopError> {
+        self.bump(limit, len).ok_or(TryPopError::Exhausted)
+    }
+
+    /// Subtracts `len` from the value.
+    #[inline]
+    pub fn release(&self, len: usize) {
+        self.update(|index| Some(((), index - len)));
+    }
+}
+
+/// Releases the critical section entered by `Cursor::update`, even if the update unwinds.
+#[cfg(feature = "critical-section")]
+struct Section(RestoreState);
+
+#[cfg(feature = "critical-section")]
+impl Drop for Section {
+    #[inline]
+    fn drop(&mut self) {
+        let state = self.0;
+        no_unwind(|| unsafe { critical_section::release(state) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cursor;
+    use TryPopError;
+
+    #[test]
+    fn bump_stops_at_limit() {
+        let mut cursor = Cursor::new(0);
+        assert_eq!(cursor.bump(5, 3), Some(0));
+        assert_eq!(cursor.bump(5, 3), None);
+        assert_eq!(cursor.bump(5, 2), Some(3));
+        assert_eq!(cursor.bump(5, 0), Some(5));
+        assert_eq!(cursor.bump(5, usize::MAX), None);
+        cursor.release(4);
+        assert_eq!(cursor.load(), 1);
+        *cursor.get_mut() = 2;
+        assert_eq!(cursor.update(|index| Some((index * 10, index + 1))), Some(20));
+        assert_eq!(cursor.load(), 3);
+    }
+
+    #[test]
+    fn bump_weak_stops_at_limit() {
+        let cursor = Cursor::new(0);
+        let mut bumped = 0;
+        while bumped < 2 {
+            match cursor.bump_weak(5, 2) {
+                Ok(index) => {
+                    assert_eq!(index, bumped * 2);
+                    bumped += 1;
+                }
+                Err(error) => assert_eq!(error, TryPopError::Contended),
+            }
+        }
+        assert_eq!(cursor.bump_weak(5, 2), Err(TryPopError::Exhausted));
+        assert_eq!(cursor.bump_weak(5, usize::MAX), Err(TryPopError::Exhausted));
+        assert_eq!(cursor.load(), 4);
+    }
+}
@@ -0,0 +1,184 @@
+//! Pops which stop succeeding once a deadline passes or a token is cancelled, for anytime
+//! algorithms.
+
+use core::error::Error;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Returns a handle which pops from this splitter, but fails with `TimedPopError::TimedOut`
+    /// once `deadline` has passed.
+    ///
+    /// Anytime algorithms (progressive refinement, best-effort simplification) can give every
+    /// thread such a handle, and stop allocating cleanly once their time budget is spent, while
+    /// telling that apart from running out of elements.
+    ///
+    /// Only available with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn with_deadline(&self, deadline: Instant) -> Deadline<'_, 'a, T> {
+        Deadline {
+            splitter: self,
+            deadline: Some(deadline),
+            cancelled: None,
+        }
+    }
+
+    /// Returns a handle which pops from this splitter, but fails with `TimedPopError::TimedOut`
+    /// once `cancelled` is set. See `with_deadline`.
+    pub fn with_cancellation<'s>(&'s self, cancelled: &'s AtomicBool) -> Deadline<'s, 'a, T> {
+        Deadline {
+            splitter: self,
+            #[cfg(feature = "std")]
+            deadline: None,
+            cancelled: Some(cancelled),
+        }
+    }
+}
+
+/// A handle which pops from a `SyncSplitter` until a deadline or a cancellation. See
+/// `SyncSplitter::with_deadline` and `SyncSplitter::with_cancellation`.
+#[derive(Clone, Copy)]
+pub struct Deadline<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s SyncSplitter<'a, T>,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+    cancelled: Option<&'s AtomicBool>,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'s, 'a: 's, T: 'a + Sync> Deadline<'s, 'a, T> {
+    /// Also makes pops fail once `cancelled` is set, replacing the handle's previous
+    /// cancellation token, if any. This combines a deadline with a cancellation.
+    #[inline]
+    pub fn cancelled_by(self, cancelled: &'s AtomicBool) -> Self {
+        Deadline {
+            cancelled: Some(cancelled),
+            ..self
+        }
+    }
+
+    /// Returns `true` if the deadline has passed or the handle was cancelled, in which case every
+    /// pop fails.
+    #[inline]
+    pub fn timed_out(&self) -> bool {
+        if self.cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Acquire)) {
+            return true;
+        }
+        #[cfg(feature = "std")]
+        {
+            if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Like `SyncSplitter::pop`, but fails once the deadline has passed.
+    #[inline]
+    pub fn pop(&self) -> Result<(&'s mut T, usize), TimedPopError> {
+        self.check(|| self.splitter.pop())
+    }
+
+    /// Like `SyncSplitter::pop_two`, but fails once the deadline has passed.
+    #[inline]
+    pub fn pop_two(&self) -> Result<((&'s mut T, &'s mut T), usize), TimedPopError> {
+        self.check(|| self.splitter.pop_two())
+    }
+
+    /// Like `SyncSplitter::pop_n`, but fails once the deadline has passed.
+    #[inline]
+    pub fn pop_n(&self, len: usize) -> Result<(&'s mut [T], usize), TimedPopError> {
+        self.check(|| self.splitter.pop_n(len))
+    }
+
+    #[inline]
+    fn check<R, F: FnOnce() -> Option<R>>(&self, pop: F) -> Result<R, TimedPopError> {
+        if self.timed_out() {
+            return Err(TimedPopError::TimedOut);
+        }
+        pop().ok_or(TimedPopError::Exhausted)
+    }
+}
+
+/// The error returned by the pops of a `Deadline` handle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TimedPopError {
+    /// Not enough elements were left in the underlying slice.
+    Exhausted,
+    /// The deadline passed, or the handle was cancelled, before the pop.
+    TimedOut,
+}
+
+impl fmt::Display for TimedPopError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            TimedPopError::Exhausted => "not enough elements left in slice",
+            TimedPopError::TimedOut => "deadline passed before the pop",
+        })
+    }
+}
+
+impl Error for TimedPopError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    #[cfg(feature = "std")]
+    use std::time::{Duration, Instant};
+    use super::TimedPopError;
+    use SyncSplitter;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pops_fail_after_the_deadline() {
+        let mut buffer = [0u32; 4];
+        let splitter = SyncSplitter::new(&mut buffer);
+        let later = splitter.with_deadline(Instant::now() + Duration::from_secs(3600));
+        assert_eq!(later.pop_n(3).map(|(_, index)| index), Ok(0));
+        assert_eq!(later.pop_two().map(|(_, index)| index), Err(TimedPopError::Exhausted));
+        assert!(!later.timed_out());
+
+        let cancelled = AtomicBool::new(false);
+        let passed = splitter.with_deadline(Instant::now()).cancelled_by(&cancelled);
+        assert!(passed.timed_out());
+        assert_eq!(passed.pop().map(|(_, index)| index), Err(TimedPopError::TimedOut));
+        assert_eq!(splitter.done(), 3);
+    }
+
+    #[test]
+    fn pops_fail_once_cancelled() {
+        let mut buffer = vec![0u32; 1_000_000];
+        let cancelled = AtomicBool::new(false);
+        let splitter = SyncSplitter::new(&mut buffer);
+        let popped = ::std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| {
+                    let handle = splitter.with_cancellation(&cancelled);
+                    scope.spawn(move || {
+                        let mut popped = 0;
+                        loop {
+                            match handle.pop() {
+                                Ok(_) => popped += 1,
+                                Err(error) => return (popped, error),
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            cancelled.store(true, Ordering::Release);
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let total = popped.iter().map(|&(popped, _)| popped).sum::<usize>();
+        assert!(popped.iter().all(|&(_, error)| error == TimedPopError::TimedOut));
+        let handle = splitter.with_cancellation(&cancelled);
+        assert_eq!(handle.pop_n(0).map(|(_, index)| index), Err(TimedPopError::TimedOut));
+        assert_eq!(splitter.done(), total);
+    }
+}
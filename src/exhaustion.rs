@@ -0,0 +1,184 @@
+//! What a splitter does when a pop doesn't fit in what's left of its slice.
+
+use alloc::boxed::Box;
+use core::fmt;
+
+use no_unwind;
+use SyncSplitter;
+
+/// What a splitter does when a pop asks for more elements than are left. See
+/// `SyncSplitter::set_exhaustion_policy`.
+///
+/// There's no `Grow` policy: a splitter only ever borrows the slice it was created with, and other
+/// threads may hold references into it, so it can't be reallocated.
+///
+/// `Panic` is only available with the `panic-on-exhaustion` feature: pops otherwise never unwind
+/// (see `SyncSplitter`), and a policy chosen at runtime can't be left out of that guarantee.
+#[derive(Default)]
+pub enum ExhaustionPolicy<'a> {
+    /// The pop returns `None` (or `TryPopError::Exhausted`). This is the default.
+    #[default]
+    ReturnNone,
+    /// The process aborts, after printing a message with the number of elements which were asked
+    /// for.
+    ///
+    /// Pops never unwind, so this can't be a catchable panic (and `#[should_panic]` tests can't
+    /// expect it): see `Panic` for that.
+    Abort,
+    /// The pop panics, with a message with the number of elements which were asked for, after the
+    /// cursor was left unchanged. This makes tests which run out of elements fail loudly, and
+    /// `#[should_panic]` tests can expect it.
+    ///
+    /// Only available with the `panic-on-exhaustion` feature, which gives up on pops never
+    /// unwinding.
+    #[cfg(feature = "panic-on-exhaustion")]
+    Panic,
+    /// The callback is called with the number of elements which were asked for, then the pop
+    /// returns `None` as usual. Like threshold callbacks, a panicking callback aborts.
+    Callback(Box<dyn Fn(usize) + Send + Sync + 'a>),
+}
+
+impl<'a> fmt::Debug for ExhaustionPolicy<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            ExhaustionPolicy::ReturnNone => "ReturnNone",
+            ExhaustionPolicy::Abort => "Abort",
+            #[cfg(feature = "panic-on-exhaustion")]
+            ExhaustionPolicy::Panic => "Panic",
+            ExhaustionPolicy::Callback(_) => "Callback(..)",
+        })
+    }
+}
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Sets what happens when a pop asks for more elements than are left in the slice.
+    ///
+    /// The policy applies to every kind of pop from this splitter (including through a `Quota` or
+    /// a `Frame`), but not to splitters returned by `pop_splitter`, which start with
+    /// `ExhaustionPolicy::ReturnNone`. A `Quota` running out isn't an exhaustion of the splitter.
+    pub fn set_exhaustion_policy(&mut self, policy: ExhaustionPolicy<'a>) {
+        self.exhaustion = policy;
+    }
+
+    /// Applies the exhaustion policy to a pop of `len` elements which didn't fit.
+    #[inline]
+    pub(crate) fn exhausted(&self, len: usize) {
+        match self.exhaustion {
+            ExhaustionPolicy::ReturnNone => {}
+            // Outside of `no_unwind`, so the panic reaches the caller of the pop.
+            #[cfg(feature = "panic-on-exhaustion")]
+            ExhaustionPolicy::Panic => exhaustion_panic(len),
+            _ => no_unwind(|| apply_policy(&self.exhaustion, len)),
+        }
+    }
+}
+
+#[cold]
+fn apply_policy(policy: &ExhaustionPolicy, len: usize) {
+    match *policy {
+        ExhaustionPolicy::Abort => exhaustion_panic(len),
+        ExhaustionPolicy::Callback(ref callback) => callback(len),
+        _ => {}
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn exhaustion_panic(len: usize) -> ! {
+    panic!("not enough elements left in slice to pop {}", len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process::Command;
+    use std::sync::Mutex;
+    use super::ExhaustionPolicy;
+    use TryPopError;
+    use SyncSplitter;
+
+    #[test]
+    fn callback_policy_applies_to_every_pop() {
+        let exhausted = Mutex::new(Vec::new());
+        let mut buffer = [0u32; 4];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.set_exhaustion_policy(ExhaustionPolicy::Callback(Box::new(|len| {
+            exhausted.lock().unwrap().push(len)
+        })));
+
+        assert!(splitter.pop_n(3).is_some());
+        assert!(splitter.pop_two().is_none());
+        assert!(splitter.pop_n(5).is_none());
+        assert!(splitter.pop_splitter(2).is_none());
+        assert_eq!(splitter.try_pop_n_weak(4), Err(TryPopError::Exhausted));
+        {
+            let quota = splitter.with_quota(3);
+            assert!(quota.pop_two().is_none());
+        }
+        {
+            let (child, _) = splitter.pop_splitter(1).unwrap();
+            assert!(child.pop_two().is_none());
+        }
+        assert!(splitter.pop().is_none());
+        assert_eq!(*exhausted.lock().unwrap(), [2, 5, 2, 4, 2, 1]);
+    }
+
+    #[test]
+    fn abort_policy_aborts() {
+        // The abort can't be caught, so the exhausting pop runs in a child process: this test
+        // binary, running just this test.
+        if env::var_os("SYNC_SPLITTER_ABORT_TEST").is_some() {
+            let mut buffer = [0u32; 2];
+            let mut splitter = SyncSplitter::new(&mut buffer);
+            splitter.set_exhaustion_policy(ExhaustionPolicy::Abort);
+            assert!(splitter.pop_two().is_some());
+            splitter.pop();
+            unreachable!("the pop didn't abort");
+        }
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "exhaustion::tests::abort_policy_aborts", "--nocapture"])
+            .env("SYNC_SPLITTER_ABORT_TEST", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        // Killed by `SIGABRT`, rather than failing the test.
+        #[cfg(unix)]
+        assert_eq!(output.status.code(), None);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("not enough elements left in slice to pop 1"), "{}", stderr);
+        assert!(!stderr.contains("the pop didn't abort"), "{}", stderr);
+    }
+
+    #[cfg(feature = "panic-on-exhaustion")]
+    #[test]
+    #[should_panic(expected = "not enough elements left in slice to pop 3")]
+    fn panic_policy_panics() {
+        let mut buffer = [0u32; 2];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.set_exhaustion_policy(ExhaustionPolicy::Panic);
+        assert!(splitter.pop().is_some());
+        assert!(splitter.pop().is_some());
+        splitter.pop_n(3);
+    }
+
+    #[cfg(feature = "panic-on-exhaustion")]
+    #[test]
+    fn panic_policy_leaves_the_splitter_usable() {
+        let mut buffer = [0u32; 2];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.set_exhaustion_policy(ExhaustionPolicy::Panic);
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            splitter.pop_n(3);
+        }));
+        assert!(result.is_err());
+        assert_eq!(splitter.pop_two().map(|(_, index)| index), Some(0));
+    }
+
+    #[test]
+    fn return_none_is_the_default() {
+        assert_eq!(
+            format!("{:?}", ExhaustionPolicy::default()),
+            "ReturnNone"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Reporting a splitter's popped count when it goes out of scope.
+
+use core::ops::{Deref, DerefMut};
+
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Wraps the splitter in a guard which calls `report` with the total number of popped elements
+    /// when it's dropped, or when `Finisher::done` is called.
+    ///
+    /// This way the count isn't lost if an early return (or `?`) skips the explicit `done()` call.
+    /// `report` can store the count wherever it's needed, e.g. `|count| *len = count` for a
+    /// `&mut usize` or `|count| len.store(count, Ordering::Release)` for an atomic.
+    pub fn finish_with<F: FnOnce(usize)>(self, report: F) -> Finisher<'a, T, F> {
+        Finisher {
+            splitter: self,
+            report: Some(report),
+        }
+    }
+}
+
+/// A splitter which reports its popped count when dropped. See `SyncSplitter::finish_with`.
+///
+/// Dereferences to the splitter, so elements are popped from it as usual.
+pub struct Finisher<'a, T: 'a + Sync, F: FnOnce(usize)> {
+    splitter: SyncSplitter<'a, T>,
+    report: Option<F>,
+}
+
+impl<'a, T: 'a + Sync, F: FnOnce(usize)> Finisher<'a, T, F> {
+    /// Reports and returns the total number of popped elements, like `SyncSplitter::done`.
+    pub fn done(mut self) -> usize {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> usize {
+        let popped = *self.splitter.next.get_mut();
+        if let Some(report) = self.report.take() {
+            report(popped);
+        }
+        popped
+    }
+}
+
+impl<'a, T: 'a + Sync, F: FnOnce(usize)> Deref for Finisher<'a, T, F> {
+    type Target = SyncSplitter<'a, T>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.splitter
+    }
+}
+
+impl<'a, T: 'a + Sync, F: FnOnce(usize)> DerefMut for Finisher<'a, T, F> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.splitter
+    }
+}
+
+impl<'a, T: 'a + Sync, F: FnOnce(usize)> Drop for Finisher<'a, T, F> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use SyncSplitter;
+
+    fn fill_until(splitter: &SyncSplitter<u32>, stop: u32) -> Option<()> {
+        for value in 0.. {
+            if value == stop {
+                return None;
+            }
+            *splitter.pop()?.0 = value;
+        }
+        Some(())
+    }
+
+    #[test]
+    fn reports_count_on_early_return() {
+        let mut buffer = [0u32; 10];
+        let mut len = 0;
+        {
+            let splitter = SyncSplitter::new(&mut buffer).finish_with(|count| len = count);
+            assert!(fill_until(&splitter, 4).is_none());
+        }
+        assert_eq!(len, 4);
+        assert_eq!(buffer[..5], [0, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn reports_count_once_from_done() {
+        let mut buffer = [0u32; 10];
+        let len = AtomicUsize::new(0);
+        let mut splitter = SyncSplitter::new(&mut buffer)
+            .finish_with(|count| assert_eq!(len.swap(count, Ordering::SeqCst), 0));
+        splitter.pop_n(7);
+        splitter.reset();
+        splitter.pop_two();
+        assert_eq!(splitter.done(), 2);
+        assert_eq!(len.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,141 @@
+//! Reusing a splitter's slice across frames.
+
+use core::ops::Deref;
+
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Rewinds the splitter to the start of its slice, so the same elements can be popped again.
+    ///
+    /// The region kept for `pop_reserved` is emptied too, but frames leave it alone.
+    ///
+    /// Returns the number of elements popped before the reset, outside of the reserved region.
+    /// Since this takes `&mut self`, all previously popped references must be gone.
+    pub fn reset(&mut self) -> usize {
+        *self.next_reserved.get_mut() = 0;
+        let popped = self.rewind_to(0);
+        self.high_water_mark = self.high_water_mark.max(popped);
+        popped
+    }
+
+    /// Returns the largest number of popped elements seen so far, across all resets and rewinds.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark.max(self.next.load())
+    }
+
+    /// Starts a frame at the current position, returning a guard which can be used to pop elements
+    /// like the splitter itself.
+    ///
+    /// Ending the frame (explicitly with `Frame::end`, or by dropping the guard) keeps the
+    /// elements popped during it, while `Frame::rewind` discards them. A typical per-frame loop
+    /// calls `reset` and then `begin_frame` at the start of every frame.
+    pub fn begin_frame(&mut self) -> Frame<'_, 'a, T> {
+        let start = self.next.load();
+        Frame {
+            splitter: self,
+            start,
+        }
+    }
+
+    fn rewind_to(&mut self, index: usize) -> usize {
+        let next = self.next.get_mut();
+        let popped = *next;
+        *next = index;
+        #[cfg(feature = "indicatif")]
+        self.rewound(index);
+        popped
+    }
+}
+
+/// A frame started with `SyncSplitter::begin_frame`.
+///
+/// Dereferences to the splitter, so elements are popped from it as usual.
+pub struct Frame<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s mut SyncSplitter<'a, T>,
+    start: usize,
+}
+
+impl<'s, 'a: 's, T: 'a + Sync> Frame<'s, 'a, T> {
+    /// Returns the offset into the original slice at which the frame started.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.splitter.offset + self.start
+    }
+
+    /// Returns the number of elements popped since the frame started.
+    #[inline]
+    pub fn popped(&self) -> usize {
+        self.splitter.next.load() - self.start
+    }
+
+    /// Ends the frame, keeping its elements, and returns the number of elements popped during it.
+    pub fn end(self) -> usize {
+        self.popped()
+    }
+
+    /// Ends the frame, discarding its elements: the splitter is rewound to the frame's start.
+    ///
+    /// Returns the number of elements which were popped during the frame.
+    pub fn rewind(self) -> usize {
+        let popped = self.splitter.rewind_to(self.start);
+        self.splitter.high_water_mark = self.splitter.high_water_mark.max(popped);
+        popped - self.start
+    }
+}
+
+impl<'s, 'a: 's, T: 'a + Sync> Deref for Frame<'s, 'a, T> {
+    type Target = SyncSplitter<'a, T>;
+
+    #[inline]
+    fn deref(&self) -> &SyncSplitter<'a, T> {
+        self.splitter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn frames_of_sub_splitters_start_in_the_original_slice() {
+        let mut buffer = [0u32; 8];
+        let splitter = SyncSplitter::new(&mut buffer);
+        splitter.pop_n(3).unwrap();
+        let (mut child, offset) = splitter.pop_splitter(4).unwrap();
+        child.pop().unwrap();
+        let frame = child.begin_frame();
+        assert_eq!((offset, frame.start()), (3, 4));
+        assert_eq!(frame.pop().map(|(_, index)| index), Some(frame.start()));
+        assert_eq!(frame.popped(), 1);
+    }
+
+    #[test]
+    fn frames_count_and_rewind() {
+        let mut buffer = [0u32; 10];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+
+        splitter.pop();
+        {
+            let frame = splitter.begin_frame();
+            assert_eq!(frame.start(), 1);
+            *frame.pop_n(3).unwrap().0.last_mut().unwrap() = 1;
+            assert_eq!(frame.popped(), 3);
+            assert_eq!(frame.end(), 3);
+        }
+        {
+            let frame = splitter.begin_frame();
+            assert_eq!(frame.pop_n(5).unwrap().1, 4);
+            assert_eq!(frame.rewind(), 5);
+        }
+        assert_eq!(splitter.high_water_mark(), 9);
+        assert_eq!(splitter.pop(), Some((&mut 0, 4)));
+
+        assert_eq!(splitter.reset(), 5);
+        assert_eq!(splitter.high_water_mark(), 9);
+        {
+            let frame = splitter.begin_frame();
+            assert_eq!(frame.pop_n(4), Some((&mut [0, 0, 0, 1][..], 0)));
+        }
+        assert_eq!(splitter.done(), 4);
+    }
+}
@@ -0,0 +1,179 @@
+//! Deduplicating nodes as they're popped, for building DAGs.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::thread;
+
+use cursor::Cursor;
+use SyncSplitter;
+
+/// Table entries are one more than the position of a stored node, or one of these.
+const EMPTY: usize = 0;
+const RESERVED: usize = usize::MAX;
+
+/// A `HashConsArena` allows multiple threads to intern nodes at the same time, storing each
+/// distinct node in the underlying slice only once.
+///
+/// Only available with the `std` feature.
+///
+/// `intern` returns the index of an equal node if one was already stored, and otherwise pops an
+/// element for the node. This makes structurally equal subgraphs share nodes, as needed by DAG
+/// builders (expression graphs, BDDs and the like). Lookups and insertions go through a lock-free
+/// hash table, so two threads interning equal nodes at the same time always get the same index.
+pub struct HashConsArena<'a, T: 'a + Sync, S = RandomState> {
+    elements: SyncSplitter<'a, T>,
+    /// An open addressing table with linear probing, with room for twice as many entries as there
+    /// are elements, so it never fills up.
+    table: Vec<Cursor>,
+    hasher: S,
+}
+
+impl<'a, T: 'a + Sync + Hash + Eq> HashConsArena<'a, T> {
+    /// Creates a new `HashConsArena` which stores nodes in `slice`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `slice.len() > isize::MAX`.
+    pub fn new(slice: &'a mut [T]) -> Self {
+        HashConsArena::with_hasher(slice, RandomState::new())
+    }
+}
+
+impl<'a, T: 'a + Sync + Hash + Eq, S: BuildHasher> HashConsArena<'a, T, S> {
+    /// Creates a new `HashConsArena` which stores nodes in `slice`, hashing them with `hasher`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `slice.len() > isize::MAX`.
+    pub fn with_hasher(slice: &'a mut [T], hasher: S) -> Self {
+        let table_len = slice.len().saturating_mul(2).next_power_of_two();
+        HashConsArena {
+            elements: SyncSplitter::new(slice),
+            table: (0..table_len).map(|_| Cursor::new(EMPTY)).collect(),
+            hasher,
+        }
+    }
+
+    /// Returns the index of a node equal to `node` in the original slice, storing `node` first if
+    /// there's no such node yet.
+    ///
+    /// Returns `None` if `node` had to be stored, but the underlying slice was exhausted.
+    pub fn intern(&self, node: T) -> Option<usize> {
+        let mask = self.table.len() - 1;
+        let mut slot = self.hasher.hash_one(&node) as usize & mask;
+        loop {
+            let entry = &self.table[slot];
+            match entry.load() {
+                EMPTY => {
+                    let reserved = entry.update(|current| {
+                        if current == EMPTY {
+                            Some(((), RESERVED))
+                        } else {
+                            None
+                        }
+                    });
+                    if reserved.is_none() {
+                        // Another thread reserved the entry first: look at it again.
+                        continue;
+                    }
+                    return match self.elements.pop() {
+                        Some((element, index)) => {
+                            *element = node;
+                            entry.update(|_| Some(((), index - self.elements.offset + 1)));
+                            Some(index)
+                        }
+                        None => {
+                            entry.update(|_| Some(((), EMPTY)));
+                            None
+                        }
+                    };
+                }
+                // The node being stored there may be equal to this one.
+                RESERVED => thread::yield_now(),
+                stored => {
+                    // Stored nodes are never written to again.
+                    if unsafe { &*self.elements.data.add(stored - 1) } == &node {
+                        return Some(stored - 1 + self.elements.offset);
+                    }
+                    slot = (slot + 1) & mask;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of distinct nodes stored so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elements.next.load()
+    }
+
+    /// Returns `true` if no nodes were stored yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Consumes the arena and returns the number of distinct nodes stored, which are at the start
+    /// of the original slice.
+    pub fn done(self) -> usize {
+        self.elements.done()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashConsArena;
+
+    #[test]
+    fn equal_nodes_are_stored_once() {
+        let mut buffer = [(0u32, 0u32); 3];
+        {
+            let arena = HashConsArena::new(&mut buffer);
+            assert_eq!(arena.intern((1, 2)), Some(0));
+            assert_eq!(arena.intern((3, 4)), Some(1));
+            assert_eq!(arena.intern((1, 2)), Some(0));
+            assert_eq!(arena.intern((5, 6)), Some(2));
+            assert_eq!(arena.intern((7, 8)), None);
+            assert_eq!(arena.intern((3, 4)), Some(1));
+            assert_eq!(arena.done(), 3);
+        }
+        assert_eq!(buffer, [(1, 2), (3, 4), (5, 6)]);
+
+        let mut empty: [u8; 0] = [];
+        assert_eq!(HashConsArena::new(&mut empty).intern(1), None);
+    }
+
+    #[test]
+    fn threads_interning_equal_nodes_get_equal_indices() {
+        let mut buffer = vec![0u64; 1000];
+        let indices = {
+            let arena = HashConsArena::new(&mut buffer);
+            let indices = ::std::thread::scope(|scope| {
+                let threads = (0..4)
+                    .map(|_| {
+                        let arena = &arena;
+                        scope.spawn(move || {
+                            (0..10_000u64)
+                                .map(|node| arena.intern(node % 1000 + 1).unwrap())
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                threads
+                    .into_iter()
+                    .map(|thread| thread.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(arena.done(), 1000);
+            indices
+        };
+        for thread in &indices {
+            assert_eq!(*thread, indices[0]);
+            for (node, &index) in thread.iter().enumerate() {
+                assert_eq!(buffer[index], node as u64 % 1000 + 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Helpers for initializing the backing buffer of a `SyncSplitter` before splitting begins.
+
+#[cfg(feature = "std")]
+use std::thread;
+
+use SyncSplitter;
+
+/// Initializes every element of `slice` to `f(index)` using `num_threads` threads.
+///
+/// Only available with the `std` feature.
+///
+/// The slice is divided into `num_threads` contiguous chunks of (almost) equal length and each
+/// chunk is written by a different thread. On NUMA machines, the operating system usually places
+/// a page on the node of the thread which first writes to it, so splitting the same way later
+/// keeps most writes node-local. Note that for this to help, the memory must not have been touched
+/// before: prefer `vec_first_touch` when allocating a fresh arena.
+///
+/// A `num_threads` of zero is treated as one.
+#[cfg(feature = "std")]
+pub fn init_first_touch<T, F>(slice: &mut [T], num_threads: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let chunk_len = chunk_len(slice.len(), num_threads);
+    let f = &f;
+    thread::scope(|scope| {
+        for (chunk_index, chunk) in slice.chunks_mut(chunk_len).enumerate() {
+            scope.spawn(move || {
+                let offset = chunk_index * chunk_len;
+                for (index, element) in chunk.iter_mut().enumerate() {
+                    *element = f(offset + index);
+                }
+            });
+        }
+    });
+}
+
+/// Allocates a `Vec` of length `len` whose elements are initialized to `f(index)` using
+/// `num_threads` threads, like `init_first_touch`.
+///
+/// Unlike `vec![value; len]`, the memory is never written by the calling thread, so every page is
+/// first touched by the thread which initializes it.
+///
+/// Only available with the `std` feature.
+///
+/// A `num_threads` of zero is treated as one.
+#[cfg(feature = "std")]
+pub fn vec_first_touch<T, F>(len: usize, num_threads: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let mut vec = Vec::with_capacity(len);
+    {
+        let uninit = &mut vec.spare_capacity_mut()[..len];
+        let chunk_len = chunk_len(len, num_threads);
+        let f = &f;
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in uninit.chunks_mut(chunk_len).enumerate() {
+                scope.spawn(move || {
+                    let offset = chunk_index * chunk_len;
+                    for (index, element) in chunk.iter_mut().enumerate() {
+                        element.write(f(offset + index));
+                    }
+                });
+            }
+        });
+    }
+    // All `len` elements were initialized above: `thread::scope` joins every thread and
+    // propagates panics, in which case the `Vec` is dropped with a length of zero.
+    unsafe { vec.set_len(len) };
+    vec
+}
+
+/// Initializes every element of `slice` to `f(index)` in parallel, using rayon's global pool.
+///
+/// For multi-gigabyte arenas, the sequential initialization done by `vec![value; len]` can be a
+/// meaningful fraction of the total build time.
+#[cfg(feature = "rayon")]
+pub fn par_init_with<T, F>(slice: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    use rayon::prelude::*;
+    slice.par_iter_mut().enumerate().for_each(|(index, element)| *element = f(index));
+}
+
+/// Allocates a `Vec` of length `len` whose elements are initialized to `f(index)` in parallel,
+/// like `par_init_with`.
+#[cfg(feature = "rayon")]
+pub fn par_vec_init_with<T, F>(len: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    use rayon::prelude::*;
+    let mut vec = Vec::with_capacity(len);
+    vec.spare_capacity_mut()[..len]
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(index, element)| {
+            element.write(f(index));
+        });
+    // All `len` elements were initialized above, or the panic was propagated and the `Vec` is
+    // dropped with a length of zero.
+    unsafe { vec.set_len(len) };
+    vec
+}
+
+#[cfg(feature = "std")]
+fn chunk_len(len: usize, num_threads: usize) -> usize {
+    let num_threads = num_threads.max(1);
+    len.div_ceil(num_threads).max(1)
+}
+
+/// Element types which can be written with non-temporal stores by `fill_streaming`.
+///
+/// Safety
+/// ===
+///
+/// Implementors must not contain any padding bytes: every byte of a value is copied as-is.
+pub unsafe trait StreamingFill: Copy {}
+
+macro_rules! impl_streaming_fill {
+    ($($type:ty),*) => {
+        $(unsafe impl StreamingFill for $type {})*
+    };
+}
+
+impl_streaming_fill!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: StreamingFill, const N: usize> StreamingFill for [T; N] {}
+
+/// Sets every element of `slice` to `value` using non-temporal (streaming) stores where the target
+/// supports them.
+///
+/// Non-temporal stores bypass the cache, so filling a very large region this way doesn't evict
+/// data which is still needed, at the cost of making the filled region slow to read back soon
+/// after. On unsupported targets, or if the size or alignment of `T` don't allow it, this falls
+/// back to a regular fill.
+pub fn fill_streaming<T: StreamingFill>(slice: &mut [T], value: T) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if x86_64::fill_streaming(slice, value) {
+            return;
+        }
+    }
+    for element in slice {
+        *element = value;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    use core::arch::x86_64::{__m128i, _mm_sfence, _mm_stream_si128};
+    use core::mem::{self, MaybeUninit};
+    use super::StreamingFill;
+
+    const VECTOR_SIZE: usize = mem::size_of::<__m128i>();
+
+    /// Returns `false` without touching `slice` if streaming stores can't be used.
+    pub fn fill_streaming<T: StreamingFill>(slice: &mut [T], value: T) -> bool {
+        let element_size = mem::size_of::<T>();
+        if element_size == 0 || !VECTOR_SIZE.is_multiple_of(element_size) {
+            return false;
+        }
+        let head_bytes = (slice.as_ptr() as usize).wrapping_neg() % VECTOR_SIZE;
+        if !head_bytes.is_multiple_of(element_size) {
+            return false;
+        }
+
+        let head_len = (head_bytes / element_size).min(slice.len());
+        let (head, rest) = slice.split_at_mut(head_len);
+        let per_vector = VECTOR_SIZE / element_size;
+        let body_len = rest.len() - rest.len() % per_vector;
+        let (body, tail) = rest.split_at_mut(body_len);
+
+        for element in head.iter_mut().chain(tail) {
+            *element = value;
+        }
+
+        let mut pattern = MaybeUninit::<__m128i>::uninit();
+        let pattern = unsafe {
+            // `T` has no padding and `per_vector` copies exactly cover the vector.
+            let elements = pattern.as_mut_ptr() as *mut T;
+            for index in 0..per_vector {
+                elements.add(index).write(value);
+            }
+            pattern.assume_init()
+        };
+        let vectors = body.as_mut_ptr() as *mut __m128i;
+        unsafe {
+            // `body` starts at a vector-aligned address and its length is a multiple of the
+            // vector size.
+            for index in 0..body_len / per_vector {
+                _mm_stream_si128(vectors.add(index), pattern);
+            }
+            _mm_sfence();
+        }
+        true
+    }
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync + StreamingFill> SyncSplitter<'a, T> {
+    /// Pops a mutable slice of a given length, fills it with `value` using `fill_streaming` and
+    /// returns it.
+    ///
+    /// Also return the returned slice's offset into the original slice.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    pub fn pop_fill_streaming(&self, len: usize, value: T) -> Option<(&mut [T], usize)> {
+        self.pop_n(len).map(|(slice, index)| {
+            fill_streaming(slice, value);
+            (slice, index)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fill_streaming;
+    #[cfg(feature = "std")]
+    use super::{init_first_touch, vec_first_touch};
+    use SyncSplitter;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn init_first_touch_writes_every_element() {
+        for &num_threads in &[0, 1, 3, 7, 100] {
+            let mut buffer = [0usize; 50];
+            init_first_touch(&mut buffer, num_threads, |index| index * 2);
+            for (index, &value) in buffer.iter().enumerate() {
+                assert_eq!(value, index * 2);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_first_touch_writes_every_element() {
+        for &num_threads in &[0, 1, 4, 64] {
+            let vec = vec_first_touch(33, num_threads, |index| index.to_string());
+            assert_eq!(vec.len(), 33);
+            for (index, value) in vec.iter().enumerate() {
+                assert_eq!(*value, index.to_string());
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_first_touch_handles_empty() {
+        let vec = vec_first_touch(0, 4, |index| index);
+        assert!(vec.is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_init_with_writes_every_element() {
+        let mut buffer = vec![0usize; 10_000];
+        super::par_init_with(&mut buffer, |index| index + 1);
+        assert!(buffer.iter().enumerate().all(|(index, &value)| value == index + 1));
+
+        let vec = super::par_vec_init_with(10_000, |index| index.to_string());
+        assert_eq!(vec.len(), 10_000);
+        assert!(vec.iter().enumerate().all(|(index, value)| *value == index.to_string()));
+    }
+
+    #[test]
+    fn fill_streaming_fills_every_offset_and_length() {
+        let mut buffer = [0u32; 64];
+        for start in 0..8 {
+            for end in start..buffer.len() {
+                for element in buffer.iter_mut() {
+                    *element = 0;
+                }
+                fill_streaming(&mut buffer[start..end], 7);
+                for (index, &value) in buffer.iter().enumerate() {
+                    assert_eq!(value, if index >= start && index < end { 7 } else { 0 });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fill_streaming_falls_back_for_unsupported_sizes() {
+        let mut buffer = [[0u8; 3]; 20];
+        fill_streaming(&mut buffer[1..], [1, 2, 3]);
+        assert_eq!(buffer[0], [0, 0, 0]);
+        assert!(buffer[1..].iter().all(|&value| value == [1, 2, 3]));
+
+        let mut buffer = [[0u16; 4]; 9];
+        fill_streaming(&mut buffer, [4, 5, 6, 7]);
+        assert!(buffer.iter().all(|&value| value == [4, 5, 6, 7]));
+    }
+
+    #[test]
+    fn pop_fill_streaming_fills_popped_slice() {
+        let mut buffer = [0u64; 10];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop();
+            assert_eq!(
+                splitter.pop_fill_streaming(6, 3),
+                Some((&mut [3u64; 6][..], 1))
+            );
+            assert_eq!(splitter.pop_fill_streaming(4, 3), None);
+            assert_eq!(splitter.done(), 7);
+        }
+        assert_eq!(buffer, [0, 3, 3, 3, 3, 3, 3, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,81 @@
+//! Pops which pair every element with its index in the original slice.
+
+use core::iter::FusedIterator;
+use core::slice;
+
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Like `pop_n`, but returns an iterator over the popped elements, each paired with its index
+    /// in the original slice.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    #[inline]
+    pub fn pop_n_iter(&self, len: usize) -> Option<PopIter<'_, T>> {
+        self.pop_n(len).map(|(slice, index)| PopIter {
+            elements: slice.iter_mut(),
+            index,
+        })
+    }
+}
+
+/// An iterator over `(index, &mut T)` pairs returned by `SyncSplitter::pop_n_iter`.
+pub struct PopIter<'s, T: 's> {
+    elements: slice::IterMut<'s, T>,
+    index: usize,
+}
+
+impl<'s, T: 's> Iterator for PopIter<'s, T> {
+    type Item = (usize, &'s mut T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = self.elements.next()?;
+        let index = self.index;
+        self.index += 1;
+        Some((index, element))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+impl<'s, T: 's> DoubleEndedIterator for PopIter<'s, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let element = self.elements.next_back()?;
+        Some((self.index + self.elements.len(), element))
+    }
+}
+
+impl<'s, T: 's> ExactSizeIterator for PopIter<'s, T> {}
+
+impl<'s, T: 's> FusedIterator for PopIter<'s, T> {}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn pop_n_iter_yields_absolute_indices() {
+        let mut buffer = [0usize; 8];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop_two();
+            let mut elements = splitter.pop_n_iter(4).unwrap();
+            assert_eq!(elements.len(), 4);
+            let (last_index, last) = elements.next_back().unwrap();
+            *last = last_index * 10;
+            assert_eq!(elements.len(), 3);
+            for (index, element) in elements {
+                *element = index;
+            }
+            assert!(splitter.pop_n_iter(3).is_none());
+            assert_eq!(splitter.pop_n_iter(0).map(|elements| elements.len()), Some(0));
+            assert_eq!(splitter.done(), 6);
+        }
+        assert_eq!(buffer, [0, 0, 2, 3, 4, 50, 0, 0]);
+    }
+}
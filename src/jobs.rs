@@ -0,0 +1,344 @@
+//! Packing type-erased closures into a byte buffer, to be run later.
+
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+
+use cursor::Cursor;
+use SyncSplitter;
+
+/// Source of unique ids, one per arena and drain, which tie handles to the jobs they refer to.
+static NEXT_ID: Cursor = Cursor::new(0);
+
+fn next_id() -> usize {
+    NEXT_ID
+        .update(|id| Some((id, id.wrapping_add(1))))
+        .unwrap_or(0)
+}
+
+/// Written at the start of every job's reservation, followed by the closure itself.
+struct Header {
+    /// Offset of the end of the reservation, where the next job's padding starts.
+    end: usize,
+    /// Offset of the closure.
+    closure: usize,
+    /// `None` once the job has run.
+    call: Option<unsafe fn(*mut u8)>,
+    drop: unsafe fn(*mut u8),
+}
+
+/// Runs the closure, after zeroing its bytes (see `JobArena::new`).
+unsafe fn call_closure<F: FnOnce()>(closure: *mut u8) {
+    let job = ptr::read(closure as *mut F);
+    ptr::write_bytes(closure, 0, mem::size_of::<F>());
+    job()
+}
+
+/// Drops the closure, then zeroes its bytes (see `JobArena::new`).
+unsafe fn drop_closure<F>(closure: *mut u8) {
+    ptr::drop_in_place(closure as *mut F);
+    ptr::write_bytes(closure, 0, mem::size_of::<F>());
+}
+
+/// A `Sync` arena of `FnOnce()` closures, packed into a single byte buffer.
+///
+/// Jobs are pushed concurrently with `push` and run later, in the order of their position in the
+/// buffer, by `run_all`. Jobs which never ran are dropped with the arena.
+///
+/// The arena is invariant in `'a`, so it can't be shrunk to push jobs which borrow data that
+/// doesn't outlive it:
+///
+/// ```rust,compile_fail
+/// use std::mem::MaybeUninit;
+/// use sync_splitter::JobArena;
+///
+/// fn push_borrowed<'short>(arena: &JobArena<'short>, name: &'short str) {
+///     let _ = arena.push(move || println!("{}", name));
+/// }
+///
+/// let mut buffer = [MaybeUninit::new(0u8); 256];
+/// let mut arena = JobArena::new(&mut buffer);
+/// {
+///     let name = String::from("freed");
+///     // Error: `name` is dropped while the arena still holds a job borrowing it.
+///     push_borrowed(&arena, &name);
+/// }
+/// arena.run_all();
+/// ```
+pub struct JobArena<'a> {
+    bytes: SyncSplitter<'a, MaybeUninit<u8>>,
+    id: usize,
+    /// Jobs borrow for `'a`, so the arena must not be covariant in it.
+    invariant: PhantomData<fn(&'a ()) -> &'a ()>,
+}
+
+/// Identifies a job pushed into a `JobArena`, until the arena's jobs are next drained.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct JobHandle {
+    id: usize,
+    offset: usize,
+}
+
+impl JobHandle {
+    /// Returns the byte offset of the job's reservation in the arena's buffer.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> JobArena<'a> {
+    /// Creates an empty arena which stores its jobs in `buffer`.
+    ///
+    /// Closures may contain padding, which leaves uninitialized bytes in the buffer, so it's a
+    /// buffer of `MaybeUninit<u8>`. Jobs which ran or were dropped have their bytes zeroed though,
+    /// so if `buffer` was initialized, it's initialized again once the arena is dropped (unless
+    /// the arena was leaked, e.g. with `mem::forget`).
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `buffer.len() > isize::MAX`.
+    pub fn new(buffer: &'a mut [MaybeUninit<u8>]) -> Self {
+        JobArena {
+            bytes: SyncSplitter::new(buffer),
+            id: next_id(),
+            invariant: PhantomData,
+        }
+    }
+
+    /// Moves `job` into the arena and returns a handle to it.
+    ///
+    /// Returns `job` back as an error if there wasn't enough space left in the buffer.
+    pub fn push<F: FnOnce() + Send + 'a>(&self, job: F) -> Result<JobHandle, F> {
+        let header_align = mem::align_of::<Header>();
+        // Reservations are aligned for the header, so the closure may need some extra padding.
+        let len = mem::size_of::<Header>()
+            .checked_add(mem::align_of::<F>().saturating_sub(header_align))
+            .and_then(|len| len.checked_add(mem::size_of::<F>()));
+        let start = len.and_then(|len| self.bytes.bump_aligned(len, header_align));
+        let (start, len) = match (start, len) {
+            (Some(start), Some(len)) => (start, len),
+            _ => return Err(job),
+        };
+        let data = self.bytes.data;
+        let closure = start + mem::size_of::<Header>();
+        let closure = closure + (data as usize + closure).wrapping_neg() % mem::align_of::<F>();
+        unsafe {
+            // The reservation covers both the header and the closure, and the padding computed
+            // above keeps each of them aligned.
+            ptr::write(
+                data.add(start) as *mut Header,
+                Header {
+                    end: start + len,
+                    closure,
+                    call: Some(call_closure::<F>),
+                    drop: drop_closure::<F>,
+                },
+            );
+            ptr::write(data.add(closure) as *mut F, job);
+        }
+        Ok(JobHandle {
+            id: self.id,
+            offset: start,
+        })
+    }
+
+    /// Returns the number of bytes used by the jobs pushed so far, including padding.
+    #[inline]
+    pub fn bytes_used(&self) -> usize {
+        self.bytes.bytes_used()
+    }
+
+    /// Runs the job identified by `handle` ahead of the others.
+    ///
+    /// Returns `false` if the job already ran, or if the handle doesn't refer to a job pushed into
+    /// this arena since its jobs were last drained.
+    pub fn run(&mut self, handle: JobHandle) -> bool {
+        if handle.id != self.id {
+            return false;
+        }
+        unsafe { self.run_at(handle.offset) }
+    }
+
+    /// Runs every job which didn't run yet, in buffer order, then empties the arena so its buffer
+    /// can be reused. Outstanding handles are invalidated.
+    ///
+    /// Returns the number of jobs which ran.
+    pub fn run_all(&mut self) -> usize {
+        let mut ran = 0;
+        let mut offset = 0;
+        while let Some(header) = self.header_after(offset) {
+            if unsafe { self.run_at(header) } {
+                ran += 1;
+            }
+            offset = unsafe { (*(self.bytes.data.add(header) as *const Header)).end };
+        }
+        self.bytes.reset();
+        self.id = next_id();
+        ran
+    }
+
+    /// Returns the offset of the first job header at or after `offset`, if any.
+    fn header_after(&mut self, offset: usize) -> Option<usize> {
+        let end = *self.bytes.next.get_mut();
+        let address = self.bytes.data as usize + offset;
+        let header = offset + address.wrapping_neg() % mem::align_of::<Header>();
+        if header < end {
+            Some(header)
+        } else {
+            None
+        }
+    }
+
+    /// Safety: `header` must be the offset of a job's header.
+    unsafe fn run_at(&mut self, header: usize) -> bool {
+        let header = &mut *(self.bytes.data.add(header) as *mut Header);
+        // The job is marked as run before calling it, so it isn't dropped again if it panics.
+        match header.call.take() {
+            Some(call) => {
+                call(self.bytes.data.add(header.closure) as *mut u8);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a> Drop for JobArena<'a> {
+    fn drop(&mut self) {
+        let mut offset = 0;
+        while let Some(header) = self.header_after(offset) {
+            let header = unsafe { &mut *(self.bytes.data.add(header) as *mut Header) };
+            if header.call.take().is_some() {
+                unsafe { (header.drop)(self.bytes.data.add(header.closure) as *mut u8) };
+            }
+            offset = header.end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{self, MaybeUninit};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use super::JobArena;
+
+    #[test]
+    fn runs_jobs_in_buffer_order() {
+        let log = &Mutex::new(Vec::new());
+        let mut buffer = [MaybeUninit::new(0u8); 1024];
+        let mut arena = JobArena::new(&mut buffer);
+        let small = 7u8;
+        let large = [1u64, 2, 3];
+        let aligned = AlignedValue(11);
+        arena.push(move || log.lock().unwrap().push(small as u64)).ok().unwrap();
+        let second = arena
+            .push(move || log.lock().unwrap().push(large.iter().sum()))
+            .ok()
+            .unwrap();
+        arena.push(move || log.lock().unwrap().push(aligned.0)).ok().unwrap();
+        arena.push(move || log.lock().unwrap().push(0)).ok().unwrap();
+        assert!(second.offset() > 0);
+
+        assert!(arena.run(second));
+        assert!(!arena.run(second));
+        assert_eq!(*log.lock().unwrap(), [6]);
+        assert_eq!(arena.run_all(), 3);
+        assert_eq!(*log.lock().unwrap(), [6, 7, 11, 0]);
+
+        assert!(!arena.run(second));
+        assert_eq!(arena.bytes_used(), 0);
+        assert_eq!(arena.run_all(), 0);
+    }
+
+    #[repr(align(64))]
+    struct AlignedValue(u64);
+
+    #[test]
+    fn push_returns_job_when_full() {
+        let mut buffer = [MaybeUninit::uninit(); 64];
+        let arena = JobArena::new(&mut buffer);
+        let mut pushed = 0;
+        let rejected = loop {
+            match arena.push(|| {}) {
+                Ok(_) => pushed += 1,
+                Err(job) => break job,
+            }
+        };
+        rejected();
+        assert!(pushed > 0);
+    }
+
+    #[test]
+    fn drops_jobs_which_never_ran() {
+        let dropped = AtomicUsize::new(0);
+        struct CountDrop<'a>(&'a AtomicUsize);
+        impl<'a> Drop for CountDrop<'a> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut buffer = [MaybeUninit::new(0u8); 1024];
+        {
+            let mut arena = JobArena::new(&mut buffer);
+            let ran = arena.push({
+                let guard = CountDrop(&dropped);
+                move || drop(guard)
+            }).ok().unwrap();
+            for _ in 0..3 {
+                let guard = CountDrop(&dropped);
+                arena.push(move || drop(guard)).ok().unwrap();
+            }
+            assert!(arena.run(ran));
+            assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        }
+        assert_eq!(dropped.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn pushes_from_many_threads() {
+        let sum = AtomicUsize::new(0);
+        let mut buffer = vec![MaybeUninit::uninit(); 1 << 16];
+        let mut arena = JobArena::new(&mut buffer);
+        ::std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (arena, sum) = (&arena, &sum);
+                scope.spawn(move || {
+                    for value in 0..100 {
+                        arena.push(move || {
+                            sum.fetch_add(thread * 100 + value, Ordering::SeqCst);
+                        }).ok().unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(arena.run_all(), 400);
+        assert_eq!(sum.load(Ordering::SeqCst), (0..400).sum::<usize>());
+    }
+
+    #[test]
+    fn consumed_jobs_are_zeroed() {
+        let log = &Mutex::new(Vec::new());
+        let mut buffer = [MaybeUninit::new(0xffu8); 256];
+        let used = {
+            let mut arena = JobArena::new(&mut buffer);
+            // The captured tuple has a byte of padding.
+            let padded = (1u8, 2u16, 3u32);
+            let job = move || log.lock().unwrap().push(padded);
+            let closure_len = mem::size_of_val(&job);
+            let ran = arena.push(job).ok().unwrap();
+            arena.push(move || log.lock().unwrap().push(padded)).ok().unwrap();
+            assert!(arena.run(ran));
+            // The second job is dropped with the arena.
+            (arena.bytes_used(), closure_len)
+        };
+        assert_eq!(*log.lock().unwrap(), [(1, 2, 3)]);
+        let (used, closure_len) = used;
+        let bytes = buffer.iter().map(|byte| unsafe { byte.assume_init() }).collect::<Vec<_>>();
+        assert!(bytes[used - closure_len..used].iter().all(|&byte| byte == 0));
+        assert!(bytes[used..].iter().all(|&byte| byte == 0xff));
+    }
+}
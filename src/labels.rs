@@ -0,0 +1,34 @@
+//! Pops tagged with a static label, to find out which category of elements used up a splitter.
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Like `pop`, but attributes the pop to `label` (e.g. `"bvh-internal"` or `"leaf-prims"`).
+    ///
+    /// With the `stats` feature, `label_usage` reports the pops and elements of each label, which
+    /// tells which category used up the space when a splitter is exhausted. Without it, this is
+    /// just `pop`.
+    #[inline]
+    pub fn pop_labeled(&self, label: &'static str) -> Option<(&mut T, usize)> {
+        let popped = self.pop();
+        self.record_label(label, 1, popped.is_some());
+        popped
+    }
+
+    /// Like `pop_n`, but attributes the pop to `label`. See `pop_labeled`.
+    #[inline]
+    pub fn pop_n_labeled(&self, label: &'static str, len: usize) -> Option<(&mut [T], usize)> {
+        let popped = self.pop_n(len);
+        self.record_label(label, len, popped.is_some());
+        popped
+    }
+
+    #[inline]
+    fn record_label(&self, label: &'static str, len: usize, succeeded: bool) {
+        #[cfg(feature = "stats")]
+        self.stats.record_label(label, len, succeeded);
+        #[cfg(not(feature = "stats"))]
+        let _ = (label, len, succeeded);
+    }
+}
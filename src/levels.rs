@@ -0,0 +1,142 @@
+//! Building trees breadth-first, one level at a time, with rayon. Available with the `rayon`
+//! feature.
+
+use core::mem;
+use rayon::prelude::*;
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Send + Sync> SyncSplitter<'a, T> {
+    /// Builds a tree breadth-first: every level below `roots` is popped as one contiguous range,
+    /// sized from the previous level's fan-out, and processed in parallel on rayon's global pool.
+    ///
+    /// `roots` is the first level, as returned by `pop_n`. For every node of a level, `fan_out`
+    /// returns its number of children; then `expand` gets the node, the index of its first child
+    /// and its children (consecutive, in the next level), to initialize them and link to them. The
+    /// build stops at the first level without children.
+    ///
+    /// Breadth-first layouts keep each level together, which makes level-order traversals (and
+    /// the top of every traversal) touch far fewer cache lines than depth-first ones.
+    ///
+    /// Returns the number of levels built, including the roots' level, or `None` if a level didn't
+    /// fit in the underlying slice. In that case, the levels above it are fully built, but the
+    /// nodes of the last of them were never passed to `expand`.
+    ///
+    /// Example
+    /// ===
+    /// ```rust
+    /// use sync_splitter::SyncSplitter;
+    ///
+    /// // A node is its depth and the index of its first child.
+    /// let mut arena = vec![(0u32, 0usize); 15];
+    /// {
+    ///     let splitter = SyncSplitter::new(&mut arena);
+    ///     let levels = splitter.build_levels(
+    ///         splitter.pop_n(1).unwrap(),
+    ///         |&(depth, _)| if depth < 3 { 2 } else { 0 },
+    ///         |node, first_child, children| {
+    ///             node.1 = first_child;
+    ///             for child in children {
+    ///                 *child = (node.0 + 1, 0);
+    ///             }
+    ///         },
+    ///     );
+    ///     assert_eq!(levels, Some(4));
+    /// }
+    /// assert_eq!(arena[1..3], [(1, 3), (1, 5)]);
+    /// assert_eq!(arena[7], (3, 0));
+    /// ```
+    pub fn build_levels<N, E>(
+        &self,
+        roots: (&mut [T], usize),
+        fan_out: N,
+        expand: E,
+    ) -> Option<usize>
+    where
+        N: Fn(&T) -> usize + Sync,
+        E: Fn(&mut T, usize, &mut [T]) + Sync,
+    {
+        let (mut level, _) = roots;
+        let mut num_levels = 1;
+        loop {
+            let fan_outs = level.par_iter().map(&fan_out).collect::<Vec<usize>>();
+            let total = fan_outs.iter().try_fold(0usize, |total, &len| total.checked_add(len))?;
+            if total == 0 {
+                return Some(num_levels);
+            }
+            let (next, mut first_child) = self.pop_n(total)?;
+            {
+                let mut rest = &mut next[..];
+                let children = fan_outs
+                    .iter()
+                    .map(|&len| {
+                        let (children, remaining) = mem::take(&mut rest).split_at_mut(len);
+                        rest = remaining;
+                        first_child += len;
+                        (first_child - len, children)
+                    })
+                    .collect::<Vec<_>>();
+                level.par_iter_mut().zip(children).for_each(|(node, (first_child, children))| {
+                    expand(node, first_child, children)
+                });
+            }
+            level = next;
+            num_levels += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn levels_are_contiguous_and_linked() {
+        // Nodes are `(level, first child, number of children)`; every node has as many children as
+        // its index in its parent.
+        let mut arena = vec![(0usize, 0usize, 0usize); 64];
+        let popped = {
+            let splitter = SyncSplitter::new(&mut arena);
+            let (roots, offset) = splitter.pop_n(3).unwrap();
+            for (index, root) in roots.iter_mut().enumerate() {
+                *root = (0, 0, index);
+            }
+            let levels = splitter.build_levels(
+                (roots, offset),
+                |&(level, _, fan_out)| if level < 3 { fan_out } else { 0 },
+                |node, first_child, children| {
+                    node.1 = first_child;
+                    for (index, child) in children.iter_mut().enumerate() {
+                        *child = (node.0 + 1, 0, index + 1);
+                    }
+                },
+            );
+            assert_eq!(levels, Some(4));
+            splitter.done()
+        };
+        arena.truncate(popped);
+
+        let mut level_starts = vec![0];
+        for (index, window) in arena.windows(2).enumerate() {
+            assert!(window[0].0 <= window[1].0);
+            if window[0].0 != window[1].0 {
+                level_starts.push(index + 1);
+            }
+        }
+        assert_eq!(level_starts, [0, 3, 6, 10]);
+        assert_eq!(arena.len(), 15);
+        for &(level, first_child, fan_out) in &arena {
+            if level < 3 {
+                for child in &arena[first_child..first_child + fan_out] {
+                    assert_eq!(child.0, level + 1);
+                }
+            }
+        }
+
+        let mut small = vec![(0usize, 0usize, 0usize); 4];
+        let splitter = SyncSplitter::new(&mut small);
+        let roots = splitter.pop_n(1).unwrap();
+        assert_eq!(splitter.build_levels(roots, |_| 2, |_, _, _| {}), None);
+    }
+}
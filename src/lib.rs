@@ -57,38 +57,204 @@
 //! // `arena` now contains all the nodes in our binary tree.
 //!
 //! ```
-#[cfg(test)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+extern crate alloc;
+#[cfg(feature = "allocator-api2")]
+extern crate allocator_api2;
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[cfg(feature = "critical-section")]
+extern crate critical_section;
+#[cfg(feature = "tree-builder")]
+extern crate crossbeam_deque;
+#[cfg(feature = "indicatif")]
+extern crate indicatif;
+#[cfg(any(test, feature = "rayon"))]
 extern crate rayon;
-
-use std::marker::PhantomData;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::slice;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::slice;
+
+use cursor::Cursor;
+
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "allocator-api2")]
+mod allocator;
+mod branded;
+mod bytes;
+mod capacity;
+mod carve;
+mod columns;
+mod compact;
+mod exhaustion;
+mod cursor;
+mod deadline;
+mod finish;
+mod frame;
+#[cfg(feature = "std")]
+mod hashcons;
+mod init;
+mod iter;
+mod jobs;
+mod labels;
+#[cfg(feature = "rayon")]
+mod levels;
+mod link;
+mod merge;
+mod mesh;
+mod pages;
+mod policy;
+mod postings;
+#[cfg(feature = "indicatif")]
+mod progress;
+mod quota;
+mod redzone;
+mod reserved;
+mod ring;
+mod rows;
+#[cfg(feature = "serde")]
+mod saved;
+mod segmented;
+mod splittable;
+#[cfg(feature = "stats")]
+mod stats;
+mod tail;
+mod threshold;
+mod tiles;
+#[cfg(feature = "tree-builder")]
+mod tree;
+mod volume;
+#[cfg(feature = "std")]
+mod wait;
+mod weak;
+#[cfg(feature = "simd")]
+mod simd;
+
+pub use branded::{BrandedArena, BrandedIndex, BrandedRange, BrandedSplitter};
+pub use bytes::TextWriter;
+pub use capacity::{binary_tree_len, bvh_len, full_kary_bvh_len, kary_tree_len};
+pub use carve::{CarveReport, Carver, Plain, Region};
+pub use columns::{ColumnId, ColumnRows, ColumnSplitter};
+pub use compact::{compact, RemapTable};
+pub use deadline::{Deadline, TimedPopError};
+pub use exhaustion::ExhaustionPolicy;
+pub use finish::Finisher;
+pub use frame::Frame;
+pub use init::{fill_streaming, StreamingFill};
+pub use iter::PopIter;
+pub use jobs::{JobArena, JobHandle};
+pub use link::AtomicIndex;
+pub use merge::{merge_arenas, merge_arenas_with, RebaseIndices};
+pub use mesh::{Indices, MeshBuilder, MeshClaim};
+pub use pages::{Page, PageSplitter};
+pub use policy::{CursorPolicy, Linear, Striped};
+pub use postings::{PostingList, PostingWriter, Postings};
+#[cfg(feature = "std")]
+pub use hashcons::HashConsArena;
+#[cfg(feature = "std")]
+pub use init::{init_first_touch, vec_first_touch};
+pub use quota::Quota;
+pub use redzone::Redzones;
+pub use ring::{RingSlot, SyncRingSplitter};
+#[cfg(feature = "async")]
+pub use ring::PopAsync;
+pub use rows::{RowSplitter, Rows};
+#[cfg(feature = "serde")]
+pub use saved::{ArenaSeed, UsedArena, UsedArenaSeed};
+pub use segmented::{SegmentUsage, SegmentedSplitter};
+pub use splittable::Splittable;
+#[cfg(feature = "std")]
+pub use splittable::MutexSplitter;
+#[cfg(feature = "stats")]
+pub use stats::{LabelUsage, PopSizeHistogram, MAX_LABELS, OTHER_LABEL, POP_SIZE_BUCKETS};
+pub use tiles::{morton_index, Tile, TileOrder, TileSplitter};
+pub use volume::{Brick, VolumeSplitter};
+pub use weak::TryPopError;
+#[cfg(feature = "rayon")]
+pub use init::{par_init_with, par_vec_init_with};
+#[cfg(feature = "tree-builder")]
+pub use tree::{Context, ParallelTreeBuilder};
 
 /// A `SyncSplitter` allows multiple threads to split a mutable slice at the same time.
 ///
 /// See the module docs for more information.
+///
+/// Popping (`pop`, `pop_two` and `pop_n`) never panics, which makes it usable from FFI callbacks
+/// and real-time threads. Code which pops run on behalf of the user (cursor policies, threshold
+/// and exhaustion callbacks, `indicatif` progress bars, and the critical section implementation
+/// used with the `critical-section` feature) aborts the process if it panics, rather than
+/// unwinding. This is checked at link time by `tests/no_panic.rs`, with no optional features and
+/// with each of those which add code to pops (`indicatif`, `stats` and `critical-section`). The
+/// `panic-on-exhaustion` feature opts out of this, for `ExhaustionPolicy::Panic`.
 pub struct SyncSplitter<'a, T: 'a + Sync> {
     data: *mut T,
     len: usize,
-    next: AtomicUsize,
+    offset: usize,
+    next: Cursor,
+    reserved: usize,
+    next_reserved: Cursor,
+    high_water_mark: usize,
+    thresholds: Vec<threshold::Threshold<'a>>,
+    exhaustion: ExhaustionPolicy<'a>,
+    cursor_policy: Option<Box<dyn CursorPolicy + Send + Sync + 'a>>,
+    #[cfg(feature = "stats")]
+    stats: stats::Stats,
+    #[cfg(feature = "indicatif")]
+    progress: Option<indicatif::ProgressBar>,
     dummy: PhantomData<&'a mut [T]>,
 }
 
+#[allow(clippy::mut_from_ref)]
 impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
     /// Creates a new `SyncSplitter` from a slice.
     ///
     /// Panics
     /// ===
     ///
-    /// If `slice.len() > isize::MAX`.
+    /// If `slice.len() > isize::MAX`. See `try_new` for a non-panicking version.
     pub fn new(slice: &'a mut [T]) -> Self {
-        assert!(slice.len() <= isize::max_value() as usize);
-        SyncSplitter {
+        match SyncSplitter::try_new(slice) {
+            Ok(splitter) => splitter,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Creates a new `SyncSplitter` from a slice, or returns an error if `slice.len() >
+    /// isize::MAX`.
+    pub fn try_new(slice: &'a mut [T]) -> Result<Self, TooLarge> {
+        if slice.len() > isize::MAX as usize {
+            return Err(TooLarge { len: slice.len() });
+        }
+        Ok(SyncSplitter {
             data: slice.as_mut_ptr(),
             len: slice.len(),
-            next: AtomicUsize::new(0),
+            offset: 0,
+            next: Cursor::new(0),
+            reserved: 0,
+            next_reserved: Cursor::new(0),
+            high_water_mark: 0,
+            thresholds: Vec::new(),
+            exhaustion: ExhaustionPolicy::ReturnNone,
+            cursor_policy: None,
+            #[cfg(feature = "stats")]
+            stats: stats::Stats::new(),
+            #[cfg(feature = "indicatif")]
+            progress: None,
             dummy: PhantomData,
-        }
+        })
     }
 
     /// Pops one mutable reference off the slice and returns it.
@@ -100,7 +266,7 @@ impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
     #[inline]
     pub fn pop(&self) -> Option<(&mut T, usize)> {
         self.bump(1).map(|index| {
-            (unsafe { &mut *self.data.offset(index as isize) }, index)
+            (unsafe { &mut *self.data.add(index) }, self.offset + index)
         })
     }
 
@@ -115,11 +281,11 @@ impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
             (
                 unsafe {
                     (
-                        &mut *self.data.offset(index as isize),
-                        &mut *self.data.offset(index as isize + 1),
+                        &mut *self.data.add(index),
+                        &mut *self.data.add(index + 1),
                     )
                 },
-                index,
+                self.offset + index,
             )
         })
     }
@@ -133,47 +299,290 @@ impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
     pub fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
         self.bump(len).map(|index| {
             (
-                unsafe { slice::from_raw_parts_mut(self.data.offset(index as isize), len) },
-                index,
+                unsafe { slice::from_raw_parts_mut(self.data.add(index), len) },
+                self.offset + index,
             )
         })
     }
 
+    /// Pops a contiguous range of a given length and returns a new splitter over it.
+    ///
+    /// Also return the range's offset into the original slice. Indices returned by the new
+    /// splitter are offsets into the original slice too, rather than into the popped range.
+    ///
+    /// This can be used to give each thread a dedicated range to pop from: if the ranges are popped
+    /// in a fixed order before the threads are started and each range is only used by one thread,
+    /// the final layout doesn't depend on scheduling. The parts of a range which weren't popped
+    /// from its splitter are left untouched, but still count towards the parent's `done()`.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    #[inline]
+    pub fn pop_splitter<'s>(&'s self, len: usize) -> Option<(SyncSplitter<'s, T>, usize)> {
+        self.bump(len).map(|index| {
+            (unsafe { self.sub_splitter(index, len) }, self.offset + index)
+        })
+    }
+
+    /// Pops consecutive ranges of the given lengths in a single reservation and returns a new
+    /// splitter over each of them, in order.
+    ///
+    /// Also return the offset of the first range into the original slice.
+    ///
+    /// This is the building block for fork-join builds with a deterministic layout: a task which
+    /// knows (from a pre-pass, or from a static arity) how many elements each of its child tasks
+    /// will need reserves all of them at once and hands each child its own splitter. As long as no
+    /// splitter is shared between concurrently running tasks, every index is derived from the task
+    /// structure alone and the resulting arena is identical across runs and thread counts.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    pub fn pop_splitters<'s>(
+        &'s self,
+        lens: &[usize],
+    ) -> Option<(Vec<SyncSplitter<'s, T>>, usize)> {
+        let total = lens.iter().try_fold(0usize, |total, &len| total.checked_add(len))?;
+        self.bump(total).map(|index| {
+            let mut start = index;
+            let splitters = lens.iter()
+                .map(|&len| {
+                    let splitter = unsafe { self.sub_splitter(start, len) };
+                    start += len;
+                    splitter
+                })
+                .collect();
+            (splitters, self.offset + index)
+        })
+    }
+
+    /// Pops consecutive slices of the given lengths in a single reservation and returns each of
+    /// them, in order, along with its offset into the original slice.
+    ///
+    /// This is the carve-up of the output array needed by bucket and radix sorts, and other
+    /// counting sort based builds: compute a histogram of bucket sizes first, then pop every
+    /// bucket at once and fill them in parallel.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    pub fn pop_buckets(&self, counts: &[usize]) -> Option<Vec<(&mut [T], usize)>> {
+        let total = counts.iter().try_fold(0usize, |total, &count| total.checked_add(count))?;
+        self.pop_n(total).map(|(mut rest, offset)| {
+            let mut start = offset;
+            counts.iter()
+                .map(|&count| {
+                    let (bucket, remaining) = mem::take(&mut rest).split_at_mut(count);
+                    rest = remaining;
+                    start += count;
+                    (bucket, start - count)
+                })
+                .collect()
+        })
+    }
+
+    /// Gives back the last `len` popped elements, starting at `index` in the original slice, so
+    /// they can be popped again.
+    ///
+    /// This only succeeds if no other pop happened after them, which is the common case when a
+    /// recursive build pops a node's children and then finds out it's a leaf. Returns whether the
+    /// elements were given back.
+    ///
+    /// Safety
+    /// ===
+    ///
+    /// `index..index + len` must have been popped from this splitter, and no references to those
+    /// elements may be used after this call.
+    pub unsafe fn undo_last(&self, index: usize, len: usize) -> bool {
+        let start = match index.checked_sub(self.offset) {
+            Some(start) => start,
+            None => return false,
+        };
+        let undone = self.next.update(|next| {
+            if start.checked_add(len) == Some(next) {
+                Some(((), start))
+            } else {
+                None
+            }
+        });
+        #[cfg(feature = "indicatif")]
+        {
+            if undone.is_some() {
+                self.retreated(start + len, start);
+            }
+        }
+        undone.is_some()
+    }
+
+    /// Returns the number of bytes popped so far, including elements skipped for alignment and the
+    /// unused parts of ranges popped with `pop_splitter`.
+    #[inline]
+    pub fn bytes_used(&self) -> usize {
+        self.next.load() * core::mem::size_of::<T>()
+    }
+
+    /// Returns the number of bytes which are still available to be popped.
+    #[inline]
+    pub fn bytes_remaining(&self) -> usize {
+        (self.len - self.next.load()) * core::mem::size_of::<T>()
+    }
+
+    /// Returns the fraction of the slice's elements which were popped so far, between `0.0` and
+    /// `1.0`.
+    ///
+    /// An empty slice is always fully utilized.
+    #[inline]
+    pub fn utilization(&self) -> f64 {
+        if self.len == 0 {
+            1.0
+        } else {
+            self.next.load() as f64 / self.len as f64
+        }
+    }
 
     /// Consumes the splitter and returns the total number of popped elements.
+    ///
+    /// Elements popped with `pop_reserved` aren't counted, and lie past this length: see
+    /// `done_with_reserved`.
     #[inline]
     pub fn done(self) -> usize {
         // This could probably be `Relaxed`. At this point, we have unique ownership of this, so all
         // the other threads must have `join`'d. But I'm not taking any chances.
-        self.next.load(Ordering::Acquire)
+        self.next.load()
     }
 
-    fn bump(&self, len: usize) -> Option<usize> {
-        loop {
-            let index = self.next.load(Ordering::Acquire);
-            if len <= self.len && index <= self.len - len {
-                if self.next.compare_and_swap(
-                    index,
-                    index + len,
-                    Ordering::AcqRel,
-                ) == index
-                {
-                    return Some(index);
+    /// Safety: `index..index + len` must have been popped from `self` and not handed out elsewhere.
+    unsafe fn sub_splitter<'s>(&'s self, index: usize, len: usize) -> SyncSplitter<'s, T> {
+        SyncSplitter {
+            data: self.data.add(index),
+            len,
+            offset: self.offset + index,
+            next: Cursor::new(0),
+            reserved: 0,
+            next_reserved: Cursor::new(0),
+            high_water_mark: 0,
+            thresholds: Vec::new(),
+            exhaustion: ExhaustionPolicy::ReturnNone,
+            cursor_policy: None,
+            #[cfg(feature = "stats")]
+            stats: stats::Stats::new(),
+            #[cfg(feature = "indicatif")]
+            progress: None,
+            dummy: PhantomData,
+        }
+    }
+
+    /// Like `bump`, but skips as many elements as needed for the first popped element to be aligned
+    /// to `align` bytes. Returns `None` if that alignment can't be reached at all.
+    #[inline]
+    fn bump_aligned(&self, len: usize, align: usize) -> Option<usize> {
+        self.bump_padded(len, align).map(|(start, _)| start)
+    }
+
+    /// Like `bump_aligned`, but also returns the number of elements skipped for alignment.
+    fn bump_padded(&self, len: usize, align: usize) -> Option<(usize, usize)> {
+        debug_assert!(align.is_power_of_two());
+        #[cfg(feature = "stats")]
+        self.stats.record(len);
+        let element_size = core::mem::size_of::<T>();
+        let reserved = self.next.update(|index| {
+            let address = (self.data as usize).wrapping_add(index.wrapping_mul(element_size));
+            let padding_bytes = address.wrapping_neg() & (align - 1);
+            let padding = match (padding_bytes, element_size) {
+                (0, _) => 0,
+                (_, 0) => return None,
+                (padding_bytes, element_size) if padding_bytes % element_size == 0 => {
+                    padding_bytes / element_size
                 }
+                _ => return None,
+            };
+            let start = index.checked_add(padding)?;
+            if len <= self.len && start <= self.len - len {
+                Some(((index, start), start + len))
             } else {
+                None
+            }
+        });
+        let (index, start) = match reserved {
+            Some(reserved) => reserved,
+            None => {
+                self.exhausted(len);
                 return None;
             }
+        };
+        self.advanced(index, start + len);
+        Some((start, start - index))
+    }
+
+    #[inline]
+    fn bump(&self, len: usize) -> Option<usize> {
+        #[cfg(feature = "stats")]
+        self.stats.record(len);
+        if let Some(ref policy) = self.cursor_policy {
+            return self.bump_placed(&**policy, len);
+        }
+        match self.next.bump(self.len, len) {
+            Some(index) => {
+                self.advanced(index, index + len);
+                Some(index)
+            }
+            None => {
+                self.exhausted(len);
+                None
+            }
         }
     }
+
+    /// Notifies observers that the cursor moved from `before` to `after`.
+    #[inline]
+    fn advanced(&self, before: usize, after: usize) {
+        self.crossed(before, after);
+        #[cfg(feature = "indicatif")]
+        self.progressed(before, after);
+    }
+}
+
+/// Runs `f` on behalf of a pop, aborting the process if it panics.
+///
+/// Pops must never unwind (see `SyncSplitter`), but they run code they don't control: user
+/// callbacks and policies, `indicatif` and the critical section implementation. Unwinding out of
+/// an `extern "C"` function aborts, and `tests/no_panic.rs` can only tell that a call doesn't
+/// unwind from the `nounwind` attribute of its callee, so this is never inlined: it only wraps
+/// calls which are out of line anyway (through a callback, or into another crate), never the
+/// pops' own compare-and-swap loops.
+#[inline(never)]
+#[allow(improper_ctypes_definitions)]
+extern "C" fn no_unwind<R, F: FnOnce() -> R>(f: F) -> R {
+    f()
+}
+
+/// The error returned by `SyncSplitter::try_new` for slices longer than `isize::MAX`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TooLarge {
+    len: usize,
+}
+
+impl TooLarge {
+    /// Returns the length of the rejected slice.
+    pub fn slice_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "slice of length {} is longer than isize::MAX",
+            self.len
+        )
+    }
 }
 
+impl Error for TooLarge {}
+
 unsafe impl<'a, T: Sync> Sync for SyncSplitter<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for SyncSplitter<'a, T> {}
 
 #[cfg(test)]
 mod tests {
     use rayon;
     use super::SyncSplitter;
-    use std::isize;
     use std::collections::HashMap;
 
     #[test]
@@ -260,6 +669,42 @@ mod tests {
     //    let _splitter = SyncSplitter::new(&mut buffer);
     //}
 
+    #[test]
+    fn reports_memory_usage() {
+        let mut buffer = [0u32; 8];
+        let splitter = SyncSplitter::new(&mut buffer);
+        assert_eq!((splitter.bytes_used(), splitter.bytes_remaining()), (0, 32));
+        assert_eq!(splitter.utilization(), 0.0);
+
+        splitter.pop_n(6);
+        assert_eq!((splitter.bytes_used(), splitter.bytes_remaining()), (24, 8));
+        assert_eq!(splitter.utilization(), 0.75);
+
+        let mut empty: [u64; 0] = [];
+        let splitter = SyncSplitter::new(&mut empty);
+        assert_eq!((splitter.bytes_used(), splitter.bytes_remaining()), (0, 0));
+        assert_eq!(splitter.utilization(), 1.0);
+    }
+
+    #[test]
+    fn try_new_accepts_isize_max() {
+        let mut buffer = [(); isize::MAX as usize];
+        assert!(SyncSplitter::try_new(&mut buffer).is_ok());
+    }
+
+    #[test]
+    fn try_new_rejects_more_than_isize_max() {
+        // Zero-sized elements make any length valid.
+        let data = ::std::ptr::NonNull::<()>::dangling().as_ptr();
+        let buffer = unsafe { ::std::slice::from_raw_parts_mut(data, usize::MAX) };
+        let error = SyncSplitter::try_new(buffer).err().unwrap();
+        assert_eq!(error.slice_len(), usize::MAX);
+        assert_eq!(
+            error.to_string(),
+            format!("slice of length {} is longer than isize::MAX", usize::MAX)
+        );
+    }
+
     #[test]
     fn isize_max_minus_one_then_pop_min_is_ok() {
         let mut buffer = [(); isize::MAX as usize - 1];
@@ -268,6 +713,172 @@ mod tests {
     }
 
 
+    #[test]
+    fn pop_splitter_returns_absolute_indices() {
+        let mut buffer = [1u32, 2, 3, 4, 5, 6, 7];
+        let splitter = SyncSplitter::new(&mut buffer);
+
+        assert_eq!(splitter.pop(), Some((&mut 1u32, 0)));
+        {
+            let (first, first_offset) = splitter.pop_splitter(4).unwrap();
+            assert_eq!(first_offset, 1);
+            assert_eq!(first.pop(), Some((&mut 2u32, 1)));
+            assert_eq!(first.pop_n(2), Some((&mut [3u32, 4u32][..], 2)));
+
+            let (nested, nested_offset) = first.pop_splitter(1).unwrap();
+            assert_eq!(nested_offset, 4);
+            assert_eq!(nested.pop_two(), None);
+            assert_eq!(nested.pop(), Some((&mut 5u32, 4)));
+            assert_eq!(nested.pop(), None);
+            assert_eq!(nested.done(), 1);
+
+            assert_eq!(first.pop(), None);
+            assert_eq!(first.done(), 4);
+        }
+        assert!(splitter.pop_splitter(3).is_none());
+        assert_eq!(splitter.pop_splitter(2).map(|(_, offset)| offset), Some(5));
+        assert_eq!(splitter.done(), 7);
+    }
+
+    #[test]
+    fn per_thread_ranges_give_deterministic_layout() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 100;
+
+        let build = || {
+            let mut arena = vec![(0usize, 0usize); THREADS * PER_THREAD];
+            {
+                let splitter = SyncSplitter::new(&mut arena);
+                let ranges = (0..THREADS)
+                    .map(|_| splitter.pop_splitter(PER_THREAD).unwrap().0)
+                    .collect::<Vec<_>>();
+                ::std::thread::scope(|scope| {
+                    for (thread, range) in ranges.into_iter().enumerate() {
+                        scope.spawn(move || {
+                            while let Some((slot, index)) = range.pop() {
+                                *slot = (thread, index);
+                            }
+                        });
+                    }
+                });
+                assert_eq!(splitter.done(), THREADS * PER_THREAD);
+            }
+            arena
+        };
+
+        let first = build();
+        for (index, &(thread, stored_index)) in first.iter().enumerate() {
+            assert_eq!(thread, index / PER_THREAD);
+            assert_eq!(stored_index, index);
+        }
+        assert_eq!(first, build());
+    }
+
+    #[test]
+    fn undo_last_only_gives_back_most_recent_pop() {
+        let mut buffer = [1u32, 2, 3, 4, 5, 6];
+        let splitter = SyncSplitter::new(&mut buffer);
+        let (_, first) = splitter.pop_two().unwrap();
+        let (_, second) = splitter.pop_n(3).unwrap();
+        unsafe {
+            assert!(!splitter.undo_last(first, 2));
+            assert!(!splitter.undo_last(second, 2));
+            assert!(splitter.undo_last(second, 3));
+            assert!(!splitter.undo_last(second, 3));
+        }
+        assert_eq!(splitter.pop_n(4), Some((&mut [3u32, 4, 5, 6][..], 2)));
+        {
+            let (range, _) = splitter.pop_splitter(0).unwrap();
+            assert!(unsafe { range.undo_last(6, 0) });
+            assert!(unsafe { !range.undo_last(5, 1) });
+        }
+        assert_eq!(splitter.done(), 6);
+    }
+
+    #[test]
+    fn pop_splitters_reserves_consecutive_ranges() {
+        let mut buffer = [1u32, 2, 3, 4, 5, 6];
+        let splitter = SyncSplitter::new(&mut buffer);
+
+        assert_eq!(splitter.pop(), Some((&mut 1u32, 0)));
+        {
+            let (ranges, offset) = splitter.pop_splitters(&[2, 0, 3]).unwrap();
+            assert_eq!(offset, 1);
+            assert_eq!(ranges.len(), 3);
+            assert_eq!(ranges[0].pop_n(2), Some((&mut [2u32, 3u32][..], 1)));
+            assert_eq!(ranges[1].pop(), None);
+            assert_eq!(ranges[2].pop(), Some((&mut 4u32, 3)));
+            assert_eq!(ranges[2].pop_two(), Some(((&mut 5u32, &mut 6u32), 4)));
+        }
+        assert!(splitter.pop_splitters(&[0, 1]).is_none());
+        assert!(splitter.pop_splitters(&[usize::MAX, 2]).is_none());
+        assert_eq!(splitter.pop_splitters(&[]).map(|(ranges, _)| ranges.len()), Some(0));
+        assert_eq!(splitter.done(), 6);
+    }
+
+    #[test]
+    fn pop_buckets_carves_consecutive_buckets() {
+        let mut buffer = [0u32; 8];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop();
+            {
+                let buckets = splitter.pop_buckets(&[3, 0, 2]).unwrap();
+                let offsets = buckets.iter().map(|&(ref bucket, offset)| (bucket.len(), offset));
+                assert_eq!(offsets.collect::<Vec<_>>(), [(3, 1), (0, 4), (2, 4)]);
+                for (bucket, offset) in buckets {
+                    bucket.iter_mut().for_each(|element| *element = offset as u32);
+                }
+            }
+            assert!(splitter.pop_buckets(&[1, 2]).is_none());
+            assert!(splitter.pop_buckets(&[usize::MAX, 2]).is_none());
+            assert_eq!(splitter.pop_buckets(&[]).map(|buckets| buckets.len()), Some(0));
+            assert_eq!(splitter.done(), 6);
+        }
+        assert_eq!(buffer, [0, 1, 1, 1, 4, 4, 0, 0]);
+    }
+
+    fn create_subtree_deterministic(splitter: &SyncSplitter<(u32, usize)>, height: u32) {
+        let (node, _) = splitter.pop().unwrap();
+        if height == 0 {
+            *node = (height, 0);
+            return;
+        }
+
+        // A complete binary tree of height `h` has `2^(h + 1) - 1` nodes.
+        let subtree_len = (1 << height) - 1;
+        let (children, first_child_index) =
+            splitter.pop_splitters(&[subtree_len, subtree_len]).unwrap();
+        *node = (height, first_child_index);
+        rayon::join(
+            || create_subtree_deterministic(&children[0], height - 1),
+            || create_subtree_deterministic(&children[1], height - 1),
+        );
+    }
+
+    #[test]
+    fn fork_join_with_pop_splitters_is_deterministic() {
+        const DEPTH: u32 = 8;
+        const EXPECTED_NODES: usize = (1 << (DEPTH + 1)) - 1;
+
+        let build = || {
+            let mut arena = vec![(0u32, 0usize); EXPECTED_NODES];
+            {
+                let splitter = SyncSplitter::new(&mut arena);
+                create_subtree_deterministic(&splitter, DEPTH);
+                assert_eq!(splitter.done(), EXPECTED_NODES);
+            }
+            arena
+        };
+
+        let first = build();
+        assert_eq!(first[0], (DEPTH, 1));
+        assert_eq!(first[1], (DEPTH - 1, 2));
+        for _ in 0..10 {
+            assert!(first == build());
+        }
+    }
+
     #[derive(Default, Copy, Clone)]
     struct Node {
         height: u32,
@@ -0,0 +1,280 @@
+//! An optional index which threads can race to set, for linking popped nodes together.
+
+use core::fmt;
+
+#[cfg(not(feature = "critical-section"))]
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "critical-section")]
+use core::cell::Cell;
+#[cfg(feature = "critical-section")]
+use critical_section::{self, Mutex};
+
+/// The stored value of an empty `AtomicIndex`.
+const NONE: u32 = u32::MAX;
+
+/// An optional index, stored in 32 bits, which can be read and set by several threads at the same
+/// time.
+///
+/// This is meant to be embedded in node structs: threads pop nodes from a splitter, then race to
+/// link them into the structure (e.g. "install this child if there's none yet") with
+/// `set_if_none` and `compare_and_set`, instead of with hand-rolled atomics. Indices must be less
+/// than `u32::MAX`, which is used for `None`.
+///
+/// With the `critical-section` feature, the index is updated inside a critical section instead of
+/// with atomic instructions, like the splitters' cursors.
+pub struct AtomicIndex {
+    #[cfg(not(feature = "critical-section"))]
+    value: AtomicU32,
+    #[cfg(feature = "critical-section")]
+    value: Mutex<Cell<u32>>,
+}
+
+impl AtomicIndex {
+    /// Creates an `AtomicIndex` holding `index`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= u32::MAX`.
+    #[inline]
+    pub fn new(index: Option<usize>) -> Self {
+        AtomicIndex::from_raw(encode(index))
+    }
+
+    /// Creates an empty `AtomicIndex`.
+    #[inline]
+    pub const fn none() -> Self {
+        AtomicIndex::from_raw(NONE)
+    }
+
+    /// Returns the index.
+    #[inline]
+    pub fn load(&self) -> Option<usize> {
+        decode(self.load_raw())
+    }
+
+    /// Sets the index to `index`, whatever it was.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= u32::MAX`.
+    #[inline]
+    pub fn store(&self, index: Option<usize>) {
+        self.swap(index);
+    }
+
+    /// Sets the index to `index` and returns the previous one.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= u32::MAX`.
+    #[inline]
+    pub fn swap(&self, index: Option<usize>) -> Option<usize> {
+        let new = encode(index);
+        decode(self.update_raw(|_| Some(new)).unwrap_or_else(|current| current))
+    }
+
+    /// Removes the index and returns it.
+    #[inline]
+    pub fn take(&self) -> Option<usize> {
+        self.swap(None)
+    }
+
+    /// Sets the index to `new` if it's `current`. Otherwise, returns the actual index.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `new >= u32::MAX`.
+    #[inline]
+    pub fn compare_and_set(
+        &self,
+        current: Option<usize>,
+        new: Option<usize>,
+    ) -> Result<(), Option<usize>> {
+        let (expected, new) = (encode(current), encode(new));
+        self.update_raw(|actual| if actual == expected { Some(new) } else { None })
+            .map(|_| ())
+            .map_err(decode)
+    }
+
+    /// Sets the index to `index` if there's none yet. Otherwise, returns the index which was set
+    /// first.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= u32::MAX`.
+    #[inline]
+    pub fn set_if_none(&self, index: usize) -> Result<(), usize> {
+        self.compare_and_set(None, Some(index)).map_err(|actual| actual.unwrap_or(index))
+    }
+
+    /// Returns the index, through a mutable reference, without any synchronization.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<usize> {
+        decode(*self.raw_mut())
+    }
+
+    /// Consumes the `AtomicIndex` and returns the index.
+    #[inline]
+    pub fn into_inner(mut self) -> Option<usize> {
+        self.get_mut()
+    }
+
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    const fn from_raw(value: u32) -> Self {
+        AtomicIndex {
+            value: AtomicU32::new(value),
+        }
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    const fn from_raw(value: u32) -> Self {
+        AtomicIndex {
+            value: Mutex::new(Cell::new(value)),
+        }
+    }
+
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    fn load_raw(&self) -> u32 {
+        self.value.load(Ordering::Acquire)
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    fn load_raw(&self) -> u32 {
+        critical_section::with(|section| self.value.borrow(section).get())
+    }
+
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    fn raw_mut(&mut self) -> &mut u32 {
+        self.value.get_mut()
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    fn raw_mut(&mut self) -> &mut u32 {
+        self.value.get_mut().get_mut()
+    }
+
+    /// Replaces the current value with `update(current)`, unless that's `None`. Returns the value
+    /// before the update, or the current value if there was no update.
+    #[cfg(not(feature = "critical-section"))]
+    #[inline]
+    fn update_raw<F: FnMut(u32) -> Option<u32>>(&self, update: F) -> Result<u32, u32> {
+        self.value.fetch_update(Ordering::AcqRel, Ordering::Acquire, update)
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[inline]
+    fn update_raw<F: FnMut(u32) -> Option<u32>>(&self, mut update: F) -> Result<u32, u32> {
+        critical_section::with(|section| {
+            let value = self.value.borrow(section);
+            let current = value.get();
+            let new = update(current).ok_or(current)?;
+            value.set(new);
+            Ok(current)
+        })
+    }
+}
+
+impl Default for AtomicIndex {
+    #[inline]
+    fn default() -> Self {
+        AtomicIndex::none()
+    }
+}
+
+impl From<Option<usize>> for AtomicIndex {
+    #[inline]
+    fn from(index: Option<usize>) -> Self {
+        AtomicIndex::new(index)
+    }
+}
+
+impl fmt::Debug for AtomicIndex {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_tuple("AtomicIndex").field(&self.load()).finish()
+    }
+}
+
+#[inline]
+fn encode(index: Option<usize>) -> u32 {
+    match index {
+        Some(index) => {
+            assert!(index < NONE as usize, "index {} doesn't fit in an AtomicIndex", index);
+            index as u32
+        }
+        None => NONE,
+    }
+}
+
+#[inline]
+fn decode(value: u32) -> Option<usize> {
+    if value == NONE {
+        None
+    } else {
+        Some(value as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AtomicIndex;
+    use SyncSplitter;
+
+    #[test]
+    fn sets_and_compares_indices() {
+        let index = AtomicIndex::default();
+        assert_eq!(index.load(), None);
+        assert_eq!(index.set_if_none(3), Ok(()));
+        assert_eq!(index.set_if_none(4), Err(3));
+        assert_eq!(index.compare_and_set(Some(4), None), Err(Some(3)));
+        assert_eq!(index.compare_and_set(Some(3), Some(5)), Ok(()));
+        assert_eq!(index.swap(Some(0)), Some(5));
+        assert_eq!(index.take(), Some(0));
+        index.store(Some(u32::MAX as usize - 1));
+        assert_eq!(format!("{:?}", index), "AtomicIndex(Some(4294967294))");
+        assert_eq!(AtomicIndex::new(Some(7)).into_inner(), Some(7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_indices_which_dont_fit() {
+        AtomicIndex::new(Some(u32::MAX as usize));
+    }
+
+    #[test]
+    fn threads_race_to_install_children() {
+        // Every thread pops a child for each parent, but only the first one gets linked.
+        let mut parents = (0..100).map(|_| AtomicIndex::none()).collect::<Vec<_>>();
+        let mut children = vec![usize::MAX; 400];
+        {
+            let splitter = SyncSplitter::new(&mut children);
+            ::std::thread::scope(|scope| {
+                for thread in 0..4 {
+                    let (parents, splitter) = (&parents, &splitter);
+                    scope.spawn(move || {
+                        for (parent_index, parent) in parents.iter().enumerate() {
+                            let (child, index) = splitter.pop().unwrap();
+                            *child = parent_index + thread * 1000;
+                            let _ = parent.set_if_none(index);
+                        }
+                    });
+                }
+            });
+        }
+        for (parent_index, parent) in parents.iter_mut().enumerate() {
+            let child = parent.get_mut().unwrap();
+            assert_eq!(children[child] % 1000, parent_index);
+        }
+    }
+}
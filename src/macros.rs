@@ -0,0 +1,228 @@
+//! Macros for popping several named elements at once, and for managing several arenas together.
+
+/// Pops several consecutive elements with a single `pop_n` and binds each of them to a name.
+///
+/// Every name is bound to a `&mut T`, optionally followed by `@ index_name` to also bind that
+/// element's index in the original slice. Like `let ... else`, the `else` block runs if the
+/// elements don't fit and must diverge (`return`, `break`, `continue` or panic).
+///
+/// Example
+/// ===
+/// ```rust
+/// #[macro_use]
+/// extern crate sync_splitter;
+///
+/// use sync_splitter::SyncSplitter;
+///
+/// fn link(splitter: &SyncSplitter<usize>) -> Option<usize> {
+///     split_pop!(splitter => root @ root_index, left @ left_index, right @ right_index else {
+///         return None;
+///     });
+///     *root = left_index;
+///     *left = right_index;
+///     *right = root_index;
+///     Some(root_index)
+/// }
+///
+/// # fn main() {
+/// let mut arena = [0; 5];
+/// {
+///     let splitter = SyncSplitter::new(&mut arena);
+///     assert_eq!(link(&splitter), Some(0));
+///     assert_eq!(link(&splitter), None);
+/// }
+/// assert_eq!(arena, [1, 2, 0, 0, 0]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! split_pop {
+    ($splitter:expr => $($name:ident $(@ $index:ident)?),+ else $else:block) => {
+        let Some(mut elements) = $splitter.pop_n_iter([$(stringify!($name)),+].len()) else $else;
+        $(
+            let ($crate::__split_pop_index!($($index)?), $name) = match elements.next() {
+                Some(element) => element,
+                None => unreachable!(),
+            };
+        )+
+    };
+}
+
+/// Expands to the pattern binding an element's index in `split_pop!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __split_pop_index {
+    () => {
+        _
+    };
+    ($index:ident) => {
+        $index
+    };
+}
+
+/// Defines a struct of arenas (`Vec` fields), along with a bundle of splitters over them which is
+/// shared as one object and a struct of per-arena counts.
+///
+/// The arenas struct gets a `splitters(&mut self)` method, returning the bundle with a
+/// `SyncSplitter` field per arena. The bundle's `done(self)` consumes every splitter, truncates
+/// each `Vec` to the elements popped from it and returns the counts. Attributes (like derives or
+/// docs) apply to the arenas struct only.
+///
+/// Example
+/// ===
+/// ```rust
+/// #[macro_use]
+/// extern crate sync_splitter;
+///
+/// splitter_bundle! {
+///     #[derive(Default)]
+///     pub struct Scene => SceneSplitters, SceneCounts {
+///         pub nodes: Vec<[u32; 2]>,
+///         pub prims: Vec<f32>,
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut scene = Scene::default();
+/// scene.nodes.resize(16, [0; 2]);
+/// scene.prims.resize(16, 0.0);
+/// let counts = {
+///     let splitters = scene.splitters();
+///     let (node, _) = splitters.nodes.pop().unwrap();
+///     let (prims, index) = splitters.prims.pop_n(2).unwrap();
+///     *node = [index as u32, 2];
+///     prims.copy_from_slice(&[1.0, 2.0]);
+///     splitters.done()
+/// };
+/// assert_eq!((counts.nodes, counts.prims), (1, 2));
+/// assert_eq!(scene.nodes, [[0, 2]]);
+/// assert_eq!(scene.prims, [1.0, 2.0]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! splitter_bundle {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $arenas:ident => $splitters:ident, $counts:ident {
+            $($field_vis:vis $field:ident: Vec<$ty:ty>),+ $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $arenas {
+            $($field_vis $field: Vec<$ty>,)+
+        }
+
+        impl $arenas {
+            /// Returns a bundle of splitters, one over each arena.
+            $vis fn splitters(&mut self) -> $splitters<'_> {
+                let arenas: *mut $arenas = self;
+                $splitters {
+                    // Every splitter borrows a different arena, and `done` only touches the
+                    // arenas once they're all consumed.
+                    $(
+                        $field: $crate::SyncSplitter::new(unsafe {
+                            (*arenas).$field.as_mut_slice()
+                        }),
+                    )+
+                    __arenas: arenas,
+                }
+            }
+        }
+
+        /// A splitter over each arena of
+        #[doc = concat!("`", stringify!($arenas), "`.")]
+        $vis struct $splitters<'a> {
+            $($field_vis $field: $crate::SyncSplitter<'a, $ty>,)+
+            __arenas: *mut $arenas,
+        }
+
+        impl<'a> $splitters<'a> {
+            /// Consumes every splitter, truncates each arena to the elements popped from it and
+            /// returns their numbers.
+            $vis fn done(self) -> $counts {
+                let counts = $counts {
+                    $($field: self.$field.done(),)+
+                };
+                let arenas = unsafe { &mut *self.__arenas };
+                $(arenas.$field.truncate(counts.$field);)+
+                counts
+            }
+        }
+
+        unsafe impl<'a> Sync for $splitters<'a> where $($ty: Sync),+ {}
+        unsafe impl<'a> Send for $splitters<'a> where $($ty: Send + Sync),+ {}
+
+        /// The number of elements popped from each arena of
+        #[doc = concat!("`", stringify!($arenas), "`.")]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+        $vis struct $counts {
+            $($field_vis $field: usize,)+
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    splitter_bundle! {
+        struct Arenas => ArenaSplitters, ArenaCounts {
+            nodes: Vec<(u32, u32)>,
+            names: Vec<u8>,
+        }
+    }
+
+    #[test]
+    fn split_pop_binds_names_and_indices() {
+        let mut buffer = [0u32; 4];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop();
+            split_pop!(splitter => first, second @ second_index else { panic!() });
+            *first = 10;
+            *second = 20;
+            assert_eq!(second_index, 2);
+
+            let mut failed = false;
+            for _ in 0..2 {
+                split_pop!(splitter => _first, _second else {
+                    failed = true;
+                    continue;
+                });
+            }
+            assert!(failed);
+            assert_eq!(splitter.done(), 3);
+        }
+        assert_eq!(buffer, [0, 10, 20, 0]);
+    }
+
+    #[test]
+    fn splitter_bundle_truncates_every_arena() {
+        let mut arenas = Arenas {
+            nodes: vec![(0, 0); 100],
+            names: vec![0; 100],
+        };
+        let counts = {
+            let splitters = arenas.splitters();
+            ::std::thread::scope(|scope| {
+                for thread in 0..4u8 {
+                    let splitters = &splitters;
+                    scope.spawn(move || {
+                        for _ in 0..10 {
+                            let (name, name_index) = splitters.names.pop_n(2).unwrap();
+                            name.copy_from_slice(&[thread, thread]);
+                            let (node, _) = splitters.nodes.pop().unwrap();
+                            *node = (name_index as u32, 2);
+                        }
+                    });
+                }
+            });
+            splitters.done()
+        };
+        assert_eq!(counts, ArenaCounts { nodes: 40, names: 80 });
+        assert_eq!((arenas.nodes.len(), arenas.names.len()), (40, 80));
+        for &(start, len) in &arenas.nodes {
+            let name = &arenas.names[start as usize..][..len as usize];
+            assert_eq!(name[0], name[1]);
+        }
+    }
+}
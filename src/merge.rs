@@ -0,0 +1,114 @@
+//! Stitching independently built arenas into a single one.
+
+use alloc::vec::Vec;
+
+/// Elements which store indices into their own arena, which must be offset when the arena is
+/// merged into a larger one (see `merge_arenas`).
+pub trait RebaseIndices {
+    /// Adds `base` to every index stored in `self`.
+    fn rebase_indices(&mut self, base: usize);
+}
+
+impl RebaseIndices for usize {
+    #[inline]
+    fn rebase_indices(&mut self, base: usize) {
+        *self += base;
+    }
+}
+
+impl<T: RebaseIndices> RebaseIndices for Option<T> {
+    #[inline]
+    fn rebase_indices(&mut self, base: usize) {
+        if let Some(index) = self {
+            index.rebase_indices(base);
+        }
+    }
+}
+
+/// Merges several arenas, built independently (e.g. one per chunk of the input, each with its own
+/// `SyncSplitter`), into one, with each arena copied after the previous.
+///
+/// The indices stored in each arena's elements are rebased with `RebaseIndices`, so they point
+/// into the merged arena. Returns the merged arena and the index at which each arena starts in it.
+pub fn merge_arenas<T, I>(arenas: I) -> (Vec<T>, Vec<usize>)
+where
+    T: RebaseIndices,
+    I: IntoIterator<Item = Vec<T>>,
+{
+    merge_arenas_with(arenas, T::rebase_indices)
+}
+
+/// Like `merge_arenas`, but rebases indices by calling `rebase` on every element, with the index
+/// at which its arena starts.
+pub fn merge_arenas_with<T, I, F>(arenas: I, mut rebase: F) -> (Vec<T>, Vec<usize>)
+where
+    I: IntoIterator<Item = Vec<T>>,
+    F: FnMut(&mut T, usize),
+{
+    let arenas = arenas.into_iter().collect::<Vec<_>>();
+    let mut merged = Vec::with_capacity(arenas.iter().map(Vec::len).sum());
+    let mut starts = Vec::with_capacity(arenas.len());
+    for mut arena in arenas {
+        let base = merged.len();
+        for element in &mut arena {
+            rebase(element, base);
+        }
+        starts.push(base);
+        merged.append(&mut arena);
+    }
+    (merged, starts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_arenas, merge_arenas_with, RebaseIndices};
+    use SyncSplitter;
+
+    /// A linked list node, with the index of the next node.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Node(u32, Option<usize>);
+
+    impl RebaseIndices for Node {
+        fn rebase_indices(&mut self, base: usize) {
+            self.1.rebase_indices(base);
+        }
+    }
+
+    fn build_list(values: &[u32]) -> Vec<Node> {
+        let mut arena = vec![Node(0, None); values.len()];
+        let len = {
+            let splitter = SyncSplitter::new(&mut arena);
+            for (position, &value) in values.iter().enumerate() {
+                let (node, index) = splitter.pop().unwrap();
+                let next = index + 1;
+                *node = Node(value, if position + 1 < values.len() { Some(next) } else { None });
+            }
+            splitter.done()
+        };
+        arena.truncate(len);
+        arena
+    }
+
+    #[test]
+    fn merged_indices_point_into_the_merged_arena() {
+        let lists = [&[1, 2, 3][..], &[], &[4, 5]];
+        let (merged, starts) = merge_arenas(lists.iter().map(|values| build_list(values)));
+        assert_eq!(starts, [0, 3, 3]);
+        assert_eq!(
+            merged,
+            [
+                Node(1, Some(1)),
+                Node(2, Some(2)),
+                Node(3, None),
+                Node(4, Some(4)),
+                Node(5, None),
+            ]
+        );
+
+        let (merged, starts) = merge_arenas_with(vec![vec![0usize, 1], vec![1, 0]], |index, base| {
+            *index += base
+        });
+        assert_eq!((merged, starts), (vec![0, 1, 3, 2], vec![0, 2]));
+        assert_eq!(merge_arenas(Vec::<Vec<usize>>::new()), (vec![], vec![]));
+    }
+}
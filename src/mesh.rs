@@ -0,0 +1,263 @@
+//! Claiming matching vertex and index ranges of a mesh together.
+
+use core::marker::PhantomData;
+use core::slice;
+
+use cursor::Cursor;
+
+/// A `MeshBuilder` allows multiple threads to claim vertices and indices of a mesh at the same
+/// time.
+///
+/// Every claim pops a range of vertices and a range of indices together, or neither, so parts of
+/// the mesh generated in parallel (e.g. terrain tiles or marching cubes cells) can write indices
+/// relative to their own vertices, and `Indices::push` adds the claim's base vertex.
+///
+/// At most `u32::MAX` vertices and indices are used.
+pub struct MeshBuilder<'a, V: 'a + Sync> {
+    vertices: *mut V,
+    num_vertices: usize,
+    indices: *mut u32,
+    num_indices: usize,
+    next_vertex: Cursor,
+    next_index: Cursor,
+    dummy: PhantomData<(&'a mut [V], &'a mut [u32])>,
+}
+
+impl<'a, V: 'a + Sync> MeshBuilder<'a, V> {
+    /// Creates a new `MeshBuilder` from a vertex buffer and an index buffer.
+    pub fn new(vertices: &'a mut [V], indices: &'a mut [u32]) -> Self {
+        let max = u32::MAX as usize;
+        MeshBuilder {
+            vertices: vertices.as_mut_ptr(),
+            num_vertices: vertices.len().min(max),
+            indices: indices.as_mut_ptr(),
+            num_indices: indices.len().min(max),
+            next_vertex: Cursor::new(0),
+            next_index: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Claims `num_vertices` consecutive vertices and `num_indices` consecutive indices.
+    ///
+    /// Returns `None` if either buffer doesn't have enough elements left. The vertices are
+    /// claimed first, and given back if the indices don't fit; unless another thread claimed
+    /// vertices in the meantime, in which case they stay claimed, but unused.
+    pub fn claim(&self, num_vertices: usize, num_indices: usize) -> Option<MeshClaim<'_, V>> {
+        let base_vertex = self.next_vertex.bump(self.num_vertices, num_vertices)?;
+        let first_index = match self.next_index.bump(self.num_indices, num_indices) {
+            Some(first_index) => first_index,
+            None => {
+                let end = base_vertex + num_vertices;
+                self.next_vertex.update(|next| {
+                    if next == end {
+                        Some(((), base_vertex))
+                    } else {
+                        None
+                    }
+                });
+                return None;
+            }
+        };
+        Some(MeshClaim {
+            vertices: unsafe {
+                slice::from_raw_parts_mut(self.vertices.add(base_vertex), num_vertices)
+            },
+            indices: Indices {
+                slots: unsafe {
+                    slice::from_raw_parts_mut(self.indices.add(first_index), num_indices)
+                },
+                len: 0,
+                base_vertex: base_vertex as u32,
+                num_vertices: num_vertices as u32,
+            },
+            base_vertex: base_vertex as u32,
+            first_index,
+        })
+    }
+
+    /// Consumes the builder and returns the total number of claimed vertices and indices.
+    pub fn done(self) -> (usize, usize) {
+        (self.next_vertex.load(), self.next_index.load())
+    }
+}
+
+unsafe impl<'a, V: Sync> Sync for MeshBuilder<'a, V> {}
+unsafe impl<'a, V: Send + Sync> Send for MeshBuilder<'a, V> {}
+
+/// Vertices and indices claimed with `MeshBuilder::claim`.
+pub struct MeshClaim<'s, V: 's> {
+    /// The claimed vertices.
+    pub vertices: &'s mut [V],
+    /// The claimed indices.
+    pub indices: Indices<'s>,
+    base_vertex: u32,
+    first_index: usize,
+}
+
+impl<'s, V: 's> MeshClaim<'s, V> {
+    /// Returns the index of the first claimed vertex in the vertex buffer.
+    #[inline]
+    pub fn base_vertex(&self) -> u32 {
+        self.base_vertex
+    }
+
+    /// Returns the offset of the first claimed index into the index buffer.
+    #[inline]
+    pub fn first_index(&self) -> usize {
+        self.first_index
+    }
+}
+
+/// The indices of a `MeshClaim`, written in order relative to the claim's vertices.
+pub struct Indices<'s> {
+    slots: &'s mut [u32],
+    len: usize,
+    base_vertex: u32,
+    num_vertices: u32,
+}
+
+impl<'s> Indices<'s> {
+    /// Returns the number of indices written so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no indices were written yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of claimed indices.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Writes the next index, pointing to the claim's `vertex`-th vertex.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If all the claimed indices were already written, or if `vertex` isn't one of the claim's
+    /// vertices.
+    #[inline]
+    pub fn push(&mut self, vertex: u32) {
+        assert!(self.len < self.slots.len(), "all {} indices written", self.slots.len());
+        assert!(
+            vertex < self.num_vertices,
+            "vertex {} out of {} claimed",
+            vertex,
+            self.num_vertices
+        );
+        // Claims end at most at `u32::MAX`, so this can't overflow.
+        self.slots[self.len] = self.base_vertex + vertex;
+        self.len += 1;
+    }
+
+    /// Writes the indices of a triangle, relative to the claim's vertices.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If fewer than three claimed indices are left, or if a vertex isn't one of the claim's
+    /// vertices.
+    #[inline]
+    pub fn triangle(&mut self, first: u32, second: u32, third: u32) {
+        self.push(first);
+        self.push(second);
+        self.push(third);
+    }
+
+    /// Returns the claimed indices, for writing absolute vertex indices directly.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u32] {
+        self.slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MeshBuilder;
+
+    #[test]
+    fn claims_offset_indices_by_base_vertex() {
+        let mut vertices = [[0f32; 2]; 8];
+        let mut indices = [0u32; 9];
+        {
+            let builder = MeshBuilder::new(&mut vertices, &mut indices);
+            let mut first = builder.claim(3, 3).unwrap();
+            assert_eq!((first.base_vertex(), first.first_index()), (0, 0));
+            first.indices.triangle(0, 1, 2);
+
+            assert!(builder.claim(6, 1).is_none());
+            assert!(builder.claim(1, 7).is_none());
+
+            let mut quad = builder.claim(4, 6).unwrap();
+            assert_eq!((quad.base_vertex(), quad.first_index()), (3, 3));
+            quad.vertices[3] = [1.0, 1.0];
+            quad.indices.triangle(0, 1, 2);
+            quad.indices.triangle(2, 3, 0);
+            assert_eq!(quad.indices.len(), 6);
+            assert_eq!(builder.done(), (7, 9));
+        }
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5, 5, 6, 3]);
+        assert_eq!(vertices[6], [1.0, 1.0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn indices_panic_when_full() {
+        let mut vertices = [0u8; 3];
+        let mut indices = [0u32; 2];
+        let builder = MeshBuilder::new(&mut vertices, &mut indices);
+        builder.claim(3, 2).unwrap().indices.triangle(0, 1, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "vertex 3 out of 3 claimed")]
+    fn indices_panic_outside_the_claim() {
+        let mut vertices = [0u8; 6];
+        let mut indices = [0u32; 3];
+        let builder = MeshBuilder::new(&mut vertices, &mut indices);
+        builder.claim(3, 0).unwrap();
+        builder.claim(3, 3).unwrap().indices.triangle(0, 1, 3);
+    }
+
+    #[test]
+    fn failed_claims_give_their_vertices_back() {
+        let mut vertices = [0u8; 4];
+        let mut indices = [0u32; 3];
+        let builder = MeshBuilder::new(&mut vertices, &mut indices);
+        assert!(builder.claim(2, 4).is_none());
+        assert!(builder.claim(4, 3).is_some());
+        assert_eq!(builder.done(), (4, 3));
+    }
+
+    #[test]
+    fn claims_from_many_threads_are_consistent() {
+        let mut vertices = vec![0usize; 4 * 3 * 100];
+        let mut indices = vec![0u32; 4 * 3 * 100];
+        {
+            let builder = MeshBuilder::new(&mut vertices, &mut indices);
+            ::std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    let builder = &builder;
+                    scope.spawn(move || {
+                        for _ in 0..100 {
+                            let mut claim = builder.claim(3, 3).unwrap();
+                            let first_index = claim.first_index();
+                            claim.vertices.iter_mut().for_each(|vertex| *vertex = first_index);
+                            claim.indices.triangle(0, 1, 2);
+                        }
+                    });
+                }
+            });
+        }
+        for (index, &vertex) in indices.iter().enumerate() {
+            assert_eq!(vertices[vertex as usize], index - index % 3);
+        }
+    }
+}
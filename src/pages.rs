@@ -0,0 +1,239 @@
+//! Splitting a byte buffer into fixed-size, aligned pages which can be freed and popped again.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use cursor::Cursor;
+
+/// Free list heads pack a tag, bumped on every change to avoid ABA problems, in the upper half of
+/// the bits and one more than the id of the first free page (or zero) in the lower half.
+const HALF_BITS: u32 = usize::BITS / 2;
+const ID_MASK: usize = (1 << HALF_BITS) - 1;
+
+/// A `PageSplitter` allows multiple threads to pop `PAGE`-byte pages, aligned to `PAGE` bytes, from
+/// a byte buffer at the same time.
+///
+/// Unlike `SyncSplitter`, popped pages can be freed with `free_page`, after which they are popped
+/// again (before any page which was never popped). Pages are identified by `u32` ids, counted from
+/// the first aligned page in the buffer.
+pub struct PageSplitter<'a, const PAGE: usize> {
+    data: *mut u8,
+    skipped: usize,
+    num_pages: usize,
+    /// The number of pages popped for the first time so far.
+    next: Cursor,
+    free_head: Cursor,
+    /// For each free page, one more than the id of the next one (or zero).
+    free_next: Vec<Cursor>,
+    dummy: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a, const PAGE: usize> PageSplitter<'a, PAGE> {
+    /// Creates a new `PageSplitter` over the aligned pages of `buffer`.
+    ///
+    /// The bytes before the first `PAGE`-aligned address and after the last whole page are never
+    /// popped. At most `u32::MAX` pages are used (or `u16::MAX` on 32-bit targets).
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `PAGE` isn't a power of two.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        assert!(PAGE.is_power_of_two(), "page size {} isn't a power of two", PAGE);
+        let skipped = buffer.as_ptr().align_offset(PAGE).min(buffer.len());
+        let num_pages = ((buffer.len() - skipped) / PAGE)
+            .min(ID_MASK - 1)
+            .min(u32::MAX as usize);
+        PageSplitter {
+            data: unsafe { buffer.as_mut_ptr().add(skipped) },
+            skipped,
+            num_pages,
+            next: Cursor::new(0),
+            free_head: Cursor::new(0),
+            free_next: (0..num_pages).map(|_| Cursor::new(0)).collect(),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the total number of pages.
+    #[inline]
+    pub fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    /// Returns the byte offset into the original buffer of the page with the given id.
+    #[inline]
+    pub fn page_offset(&self, id: u32) -> usize {
+        self.skipped + id as usize * PAGE
+    }
+
+    /// Pops a page, preferring ones which were freed over ones which were never popped.
+    ///
+    /// Also returns the page's id.
+    ///
+    /// Returns `None` if every page is in use.
+    pub fn pop_page(&self) -> Option<(Page<'_, PAGE>, u32)> {
+        let id = self.pop_free().or_else(|| self.next.bump(self.num_pages, 1))?;
+        let page = Page {
+            // Each id is handed out by at most one pop until its page is freed again.
+            bytes: unsafe { &mut *(self.data.add(id * PAGE) as *mut [u8; PAGE]) },
+            id: id as u32,
+        };
+        Some((page, id as u32))
+    }
+
+    /// Frees a page popped from this splitter, so it can be popped again.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If the page wasn't popped from this splitter.
+    pub fn free_page(&self, page: Page<'_, PAGE>) {
+        let id = page.id as usize;
+        assert!(
+            id < self.num_pages && page.bytes.as_ptr() == unsafe { self.data.add(id * PAGE) },
+            "page {} wasn't popped from this splitter",
+            id
+        );
+        self.free_head.update(|head| {
+            self.free_next[id].update(|_| Some(((), head & ID_MASK)));
+            Some(((), next_tag(head) | (id + 1)))
+        });
+    }
+
+    fn pop_free(&self) -> Option<usize> {
+        self.free_head.update(|head| {
+            let id = (head & ID_MASK).checked_sub(1)?;
+            // If the page was popped concurrently, `head`'s tag changed and the update is retried.
+            let next = self.free_next[id].load();
+            Some((id, next_tag(head) | next))
+        })
+    }
+}
+
+#[inline]
+fn next_tag(head: usize) -> usize {
+    ((head >> HALF_BITS).wrapping_add(1) << HALF_BITS) & !ID_MASK
+}
+
+unsafe impl<'a, const PAGE: usize> Sync for PageSplitter<'a, PAGE> {}
+unsafe impl<'a, const PAGE: usize> Send for PageSplitter<'a, PAGE> {}
+
+/// A page popped from a `PageSplitter`.
+///
+/// Dereferences to the page's bytes. Dropping it doesn't free the page: pass it to
+/// `PageSplitter::free_page` for that.
+pub struct Page<'s, const PAGE: usize> {
+    bytes: &'s mut [u8; PAGE],
+    id: u32,
+}
+
+impl<'s, const PAGE: usize> Page<'s, PAGE> {
+    /// Returns the page's id.
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns the page's bytes for as long as the splitter is borrowed. The page can't be freed
+    /// afterwards.
+    #[inline]
+    pub fn into_mut(self) -> &'s mut [u8; PAGE] {
+        self.bytes
+    }
+}
+
+impl<'s, const PAGE: usize> Deref for Page<'s, PAGE> {
+    type Target = [u8; PAGE];
+
+    #[inline]
+    fn deref(&self) -> &[u8; PAGE] {
+        self.bytes
+    }
+}
+
+impl<'s, const PAGE: usize> DerefMut for Page<'s, PAGE> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8; PAGE] {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageSplitter;
+
+    #[repr(align(64))]
+    struct Aligned([u8; 4 * 64]);
+
+    #[test]
+    fn pages_are_aligned_and_reused() {
+        let mut buffer = Aligned([0; 4 * 64]);
+        let buffer = &mut buffer.0;
+        let splitter = PageSplitter::<64>::new(&mut buffer[1..]);
+        assert_eq!(splitter.num_pages(), 3);
+        assert_eq!(splitter.page_offset(0) + 1, 64);
+
+        let (mut first, first_id) = splitter.pop_page().unwrap();
+        let (second, second_id) = splitter.pop_page().unwrap();
+        let (third, _) = splitter.pop_page().unwrap();
+        assert!(splitter.pop_page().is_none());
+        assert_eq!((first_id, second_id, third.id()), (0, 1, 2));
+        assert_eq!(first.as_ptr() as usize % 64, 0);
+        first[0] = 1;
+
+        splitter.free_page(second);
+        splitter.free_page(first);
+        let (again, again_id) = splitter.pop_page().unwrap();
+        assert_eq!((again_id, again[0]), (0, 1));
+        assert_eq!(splitter.pop_page().map(|(_, id)| id), Some(1));
+        assert!(splitter.pop_page().is_none());
+        third.into_mut()[63] = 3;
+        assert_eq!(buffer[3 * 64 + 63], 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn free_page_rejects_foreign_pages() {
+        let mut first = vec![0u8; 128];
+        let mut second = vec![0u8; 128];
+        let first = PageSplitter::<32>::new(&mut first);
+        let second = PageSplitter::<32>::new(&mut second);
+        let (page, _) = first.pop_page().unwrap();
+        second.free_page(page);
+    }
+
+    #[test]
+    fn pops_and_frees_from_many_threads() {
+        let mut buffer = vec![0u8; 16 * 256];
+        let splitter = PageSplitter::<256>::new(&mut buffer);
+        let pages = splitter.num_pages();
+        ::std::thread::scope(|scope| {
+            for thread in 0..4u8 {
+                let splitter = &splitter;
+                scope.spawn(move || {
+                    for _ in 0..1000 {
+                        let mut held = Vec::new();
+                        while let Some((mut page, _)) = splitter.pop_page() {
+                            page.iter_mut().for_each(|byte| *byte = thread);
+                            held.push(page);
+                            if held.len() == 3 {
+                                break;
+                            }
+                        }
+                        for page in held {
+                            assert!(page.iter().all(|&byte| byte == thread));
+                            splitter.free_page(page);
+                        }
+                    }
+                });
+            }
+        });
+        let mut popped = 0;
+        while splitter.pop_page().is_some() {
+            popped += 1;
+        }
+        assert_eq!(popped, pages);
+    }
+}
@@ -0,0 +1,169 @@
+//! Customizing where in a splitter's slice each pop is placed.
+
+use alloc::boxed::Box;
+
+use no_unwind;
+use SyncSplitter;
+
+/// Decides where a splitter places each pop. See `SyncSplitter::set_cursor_policy`.
+///
+/// The splitter still hands out disjoint elements in ascending order whatever the policy returns:
+/// a placement before the current position, or one which doesn't fit, fails the pop instead. So
+/// policies can't break the splitter's guarantees, and implementing one needs no `unsafe`.
+/// That's also why there are no policies which pop from the end of the slice, or from per-thread
+/// shards of it: `done` and `undo_last` rely on pops ascending from the start.
+pub trait CursorPolicy {
+    /// Returns the index at which to place a pop of `len` elements, given the index of the first
+    /// element which wasn't popped yet, `next`. The elements between `next` and the returned index
+    /// are skipped, like padding for alignment.
+    ///
+    /// This is called on every attempt to claim the elements, so it may be called several times
+    /// per pop when threads contend. Like threshold callbacks, a panicking policy aborts.
+    fn place(&self, next: usize, len: usize) -> usize;
+}
+
+/// Places every pop right after the previous one. This is what splitters do without a policy.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Linear;
+
+impl CursorPolicy for Linear {
+    #[inline]
+    fn place(&self, next: usize, _len: usize) -> usize {
+        next
+    }
+}
+
+/// Keeps pops from straddling the boundaries between stripes of `stripe` elements, by moving a
+/// pop which would straddle one to the start of the next stripe.
+///
+/// With stripes as large as a cache line (or a page), small pops never share one with each
+/// other's neighbors across a boundary, at the cost of the skipped elements. Pops larger than a
+/// stripe start at a stripe boundary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Striped {
+    /// The number of elements per stripe. A stripe of zero behaves like `Linear`.
+    pub stripe: usize,
+}
+
+impl CursorPolicy for Striped {
+    #[inline]
+    fn place(&self, next: usize, len: usize) -> usize {
+        if self.stripe == 0 {
+            return next;
+        }
+        let offset = next % self.stripe;
+        if offset == 0 || offset.saturating_add(len) <= self.stripe {
+            next
+        } else {
+            next.saturating_add(self.stripe - offset)
+        }
+    }
+}
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Sets where pops are placed in the slice.
+    ///
+    /// The policy applies to `pop`, `pop_two`, `pop_n`, and the pops built on them (like
+    /// `pop_splitter`, `pop_buckets` and pops through a `Quota` or a `Frame`). Aligned pops and
+    /// `try_pop_weak` / `try_pop_n_weak` always place their elements right after the previous pop.
+    /// Splitters returned by `pop_splitter` start without a policy.
+    pub fn set_cursor_policy<P: CursorPolicy + Send + Sync + 'a>(&mut self, policy: P) {
+        self.cursor_policy = Some(Box::new(policy));
+    }
+
+    /// Like `bump`, but places the pop with `policy`.
+    pub(crate) fn bump_placed(
+        &self,
+        policy: &(dyn CursorPolicy + Send + Sync),
+        len: usize,
+    ) -> Option<usize> {
+        let reserved = self.next.update(|next| {
+            let start = no_unwind(|| policy.place(next, len));
+            if start >= next && len <= self.len && start <= self.len - len {
+                Some(((next, start), start + len))
+            } else {
+                None
+            }
+        });
+        match reserved {
+            Some((next, start)) => {
+                self.advanced(next, start + len);
+                Some(start)
+            }
+            None => {
+                self.exhausted(len);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CursorPolicy, Linear, Striped};
+    use SyncSplitter;
+
+    #[test]
+    fn striped_pops_dont_straddle_stripes() {
+        let mut buffer = [0u32; 16];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.set_cursor_policy(Striped { stripe: 4 });
+        assert_eq!(splitter.pop_n(3).map(|(_, index)| index), Some(0));
+        assert_eq!(splitter.pop_two().map(|(_, index)| index), Some(4));
+        assert_eq!(splitter.pop_two().map(|(_, index)| index), Some(6));
+        assert_eq!(splitter.pop_n(5).map(|(_, index)| index), Some(8));
+        assert_eq!(splitter.pop().map(|(_, index)| index), Some(13));
+        assert!(splitter.pop_n(3).is_none());
+        assert_eq!(splitter.done(), 14);
+
+        assert_eq!(Linear.place(5, 3), 5);
+        assert_eq!(Striped { stripe: 0 }.place(5, 3), 5);
+    }
+
+    /// Places every pop after a gap of one element, from many threads at once.
+    struct Spaced;
+
+    impl CursorPolicy for Spaced {
+        fn place(&self, next: usize, _len: usize) -> usize {
+            next + 1
+        }
+    }
+
+    /// Tries to place pops over each other, which must fail them.
+    struct Overlapping;
+
+    impl CursorPolicy for Overlapping {
+        fn place(&self, next: usize, _len: usize) -> usize {
+            next.saturating_sub(1)
+        }
+    }
+
+    #[test]
+    fn custom_policies_keep_pops_disjoint() {
+        let mut buffer = vec![0usize; 1000];
+        {
+            let mut splitter = SyncSplitter::new(&mut buffer);
+            splitter.set_cursor_policy(Spaced);
+            ::std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    let splitter = &splitter;
+                    scope.spawn(move || {
+                        while let Some((element, index)) = splitter.pop() {
+                            *element += index;
+                        }
+                    });
+                }
+            });
+            assert_eq!(splitter.done(), 1000);
+        }
+        for (index, &element) in buffer.iter().enumerate() {
+            assert_eq!(element, if index % 2 == 1 { index } else { 0 });
+        }
+
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        assert!(splitter.pop().is_some());
+        splitter.set_cursor_policy(Overlapping);
+        assert!(splitter.pop().is_none());
+        assert_eq!(splitter.done(), 1);
+    }
+}
@@ -0,0 +1,236 @@
+//! Appending delta- and varint-encoded integer lists to a byte arena from many threads.
+
+use core::mem;
+
+use SyncSplitter;
+
+impl<'a> SyncSplitter<'a, u8> {
+    /// Returns a handle which appends encoded posting lists to this splitter.
+    ///
+    /// Each thread should use its own writer. To pop less often, a writer pops chunks of
+    /// `chunk_len` bytes and packs lists into them, only popping a list's bytes separately if the
+    /// list is large. A list never straddles chunks: the unused end of a chunk is left untouched,
+    /// but still counts towards `done()`.
+    pub fn posting_writer(&self, chunk_len: usize) -> PostingWriter<'_, 'a> {
+        PostingWriter {
+            splitter: self,
+            chunk: &mut [],
+            chunk_offset: 0,
+            chunk_len,
+        }
+    }
+}
+
+/// Appends posting lists to a byte splitter. See `SyncSplitter::posting_writer`.
+pub struct PostingWriter<'s, 'a: 's> {
+    splitter: &'s SyncSplitter<'a, u8>,
+    /// The unused part of the current chunk, and its offset into the original slice.
+    chunk: &'s mut [u8],
+    chunk_offset: usize,
+    chunk_len: usize,
+}
+
+impl<'s, 'a: 's> PostingWriter<'s, 'a> {
+    /// Encodes `values` and appends them to the arena as a single, contiguous run, returning where
+    /// it was stored.
+    ///
+    /// Every value is stored as the difference from the previous one (or from zero, for the first)
+    /// as a LEB128 varint, so sorted lists of close values take very little space. Unsorted values
+    /// are allowed but encode poorly.
+    ///
+    /// Returns `None` if not enough bytes were left in the underlying slice.
+    pub fn write(&mut self, values: &[u64]) -> Option<PostingList> {
+        let len = deltas(values).map(varint_len).sum::<usize>();
+        let (region, offset) = self.reserve(len)?;
+        let mut region = &mut region[..];
+        for delta in deltas(values) {
+            region = write_varint(region, delta);
+        }
+        Some(PostingList { offset, len })
+    }
+
+    fn reserve(&mut self, len: usize) -> Option<(&'s mut [u8], usize)> {
+        if len > self.chunk.len() {
+            // Lists larger than half a chunk get their own pop, so at most half a chunk is wasted.
+            if len > self.chunk_len / 2 {
+                return self.splitter.pop_n(len);
+            }
+            let (chunk, offset) = self.splitter.pop_n(self.chunk_len)?;
+            self.chunk = chunk;
+            self.chunk_offset = offset;
+        }
+        let (region, rest) = mem::take(&mut self.chunk).split_at_mut(len);
+        let offset = self.chunk_offset;
+        self.chunk = rest;
+        self.chunk_offset += len;
+        Some((region, offset))
+    }
+}
+
+/// Where a posting list was stored in the arena by `PostingWriter::write`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PostingList {
+    offset: usize,
+    len: usize,
+}
+
+impl PostingList {
+    /// Returns the offset of the list's first byte in the original slice.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the number of bytes taken by the encoded list.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list has no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decodes the list's values from `arena`, the slice the list was written to.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If the list is out of `arena`'s bounds.
+    pub fn decode<'b>(&self, arena: &'b [u8]) -> Postings<'b> {
+        Postings {
+            bytes: &arena[self.offset..self.offset + self.len],
+            previous: 0,
+        }
+    }
+}
+
+/// An iterator over the values of a `PostingList`, returned by `PostingList::decode`.
+#[derive(Clone, Debug)]
+pub struct Postings<'b> {
+    bytes: &'b [u8],
+    previous: u64,
+}
+
+impl<'b> Iterator for Postings<'b> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let mut delta = 0u64;
+        for (index, &byte) in self.bytes.iter().enumerate() {
+            delta |= u64::from(byte & 0x7f).checked_shl(7 * index as u32).unwrap_or(0);
+            if byte & 0x80 == 0 {
+                self.bytes = &self.bytes[index + 1..];
+                self.previous = self.previous.wrapping_add(delta);
+                return Some(self.previous);
+            }
+        }
+        // Truncated (or empty) input.
+        self.bytes = &[];
+        None
+    }
+}
+
+fn deltas(values: &[u64]) -> impl Iterator<Item = u64> + '_ {
+    values.iter().scan(0u64, |previous, &value| {
+        let delta = value.wrapping_sub(*previous);
+        *previous = value;
+        Some(delta)
+    })
+}
+
+fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Writes `value` at the start of `bytes` and returns the rest.
+fn write_varint(bytes: &mut [u8], mut value: u64) -> &mut [u8] {
+    let mut index = 0;
+    while value >= 0x80 {
+        bytes[index] = value as u8 | 0x80;
+        value >>= 7;
+        index += 1;
+    }
+    bytes[index] = value as u8;
+    &mut bytes[index + 1..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::varint_len;
+    use SyncSplitter;
+
+    #[test]
+    fn varint_lengths() {
+        assert_eq!(varint_len(0), 1);
+        assert_eq!(varint_len(127), 1);
+        assert_eq!(varint_len(128), 2);
+        assert_eq!(varint_len(u64::MAX), 10);
+    }
+
+    #[test]
+    fn writes_lists_which_decode_to_their_values() {
+        let lists: Vec<Vec<u64>> = vec![
+            vec![1, 2, 3, 100, 1000, 1_000_000],
+            vec![],
+            vec![u64::MAX, 0, 5],
+            (0..300).map(|value| value * 3).collect(),
+        ];
+        let mut buffer = vec![0u8; 1024];
+        let written = {
+            let splitter = SyncSplitter::new(&mut buffer);
+            let written = {
+                let mut writer = splitter.posting_writer(64);
+                lists.iter()
+                    .map(|list| writer.write(list).unwrap())
+                    .collect::<Vec<_>>()
+            };
+            // Alternating values encode to 10 bytes each.
+            let large = (0..200).map(|index| index % 2 * u64::MAX).collect::<Vec<_>>();
+            assert!(splitter.posting_writer(64).write(&large).is_none());
+            written
+        };
+        assert_eq!(written[0].offset(), 0);
+        assert!(written[1].is_empty());
+        assert_eq!(written[2].offset(), written[0].len());
+        for (list, written) in lists.iter().zip(&written) {
+            assert_eq!(written.decode(&buffer).collect::<Vec<_>>(), *list);
+        }
+    }
+
+    #[test]
+    fn writes_from_many_threads() {
+        let mut buffer = vec![0u8; 1 << 16];
+        let written = {
+            let splitter = SyncSplitter::new(&mut buffer);
+            ::std::thread::scope(|scope| {
+                let threads = (0..4u64)
+                    .map(|thread| {
+                        let splitter = &splitter;
+                        scope.spawn(move || {
+                            let mut writer = splitter.posting_writer(256);
+                            (0..100u64)
+                                .map(|list| {
+                                    let values = (0..list)
+                                        .map(|value| thread * value)
+                                        .collect::<Vec<_>>();
+                                    let written = writer.write(&values).unwrap();
+                                    (values, written)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                threads
+                    .into_iter()
+                    .flat_map(|thread| thread.join().unwrap())
+                    .collect::<Vec<_>>()
+            })
+        };
+        for (values, written) in written {
+            assert_eq!(written.decode(&buffer).collect::<Vec<_>>(), values);
+        }
+    }
+}
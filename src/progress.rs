@@ -0,0 +1,71 @@
+//! Driving an `indicatif` progress bar from a splitter, available with the `indicatif` feature.
+
+use indicatif::ProgressBar;
+
+use no_unwind;
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Ties `bar` to the number of popped elements: its length is set to the slice's length and
+    /// every pop advances it, so no extra bookkeeping is needed to display the build's progress.
+    ///
+    /// Progress bars are cheap to clone, so pass a clone to keep using the bar (e.g. to call
+    /// `finish`) once the splitter is done.
+    pub fn set_progress_bar(&mut self, bar: ProgressBar) {
+        bar.set_length(self.len as u64);
+        bar.set_position(*self.next.get_mut() as u64);
+        self.progress = Some(bar);
+    }
+
+    #[inline]
+    pub(crate) fn progressed(&self, before: usize, after: usize) {
+        if let Some(ref bar) = self.progress {
+            no_unwind(|| bar.inc((after - before) as u64));
+        }
+    }
+
+    #[inline]
+    pub(crate) fn retreated(&self, before: usize, after: usize) {
+        if let Some(ref bar) = self.progress {
+            bar.dec((before - after) as u64);
+        }
+    }
+
+    pub(crate) fn rewound(&self, index: usize) {
+        if let Some(ref bar) = self.progress {
+            bar.set_position(index as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indicatif::ProgressBar;
+    use SyncSplitter;
+
+    #[test]
+    fn progress_bar_follows_pops() {
+        let bar = ProgressBar::hidden();
+        let mut buffer = vec![0u32; 1000];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.pop_n(10);
+        splitter.set_progress_bar(bar.clone());
+        assert_eq!((bar.position(), bar.length()), (10, Some(1000)));
+
+        ::std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        splitter.pop_two();
+                    }
+                });
+            }
+        });
+        assert_eq!(bar.position(), 410);
+
+        splitter.reset();
+        assert_eq!(bar.position(), 0);
+        splitter.pop();
+        assert_eq!(bar.position(), 1);
+    }
+}
@@ -0,0 +1,116 @@
+//! Limiting how many elements a thread or group of tasks may pop from a shared splitter.
+
+use cursor::Cursor;
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Returns a handle which pops from this splitter, but fails once `limit` elements were popped
+    /// through it, even if the splitter itself still has elements left.
+    ///
+    /// Give each thread (or each group of tasks, since the handle is `Sync`) its own handle to stop
+    /// a single misbehaving producer from exhausting the whole slice.
+    pub fn with_quota(&self, limit: usize) -> Quota<'_, 'a, T> {
+        Quota {
+            splitter: self,
+            limit,
+            used: Cursor::new(0),
+        }
+    }
+}
+
+/// A handle which pops from a `SyncSplitter`, up to a limit. See `SyncSplitter::with_quota`.
+pub struct Quota<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s SyncSplitter<'a, T>,
+    limit: usize,
+    used: Cursor,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'s, 'a: 's, T: 'a + Sync> Quota<'s, 'a, T> {
+    /// Returns the maximum number of elements which can be popped through this handle.
+    #[inline]
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the number of elements popped through this handle so far.
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.used.load()
+    }
+
+    /// Returns the number of elements which can still be popped through this handle (if the
+    /// splitter has enough left).
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.limit - self.used()
+    }
+
+    /// Like `SyncSplitter::pop`, but also returns `None` if the quota was exhausted.
+    #[inline]
+    pub fn pop(&self) -> Option<(&'s mut T, usize)> {
+        self.charge(1, || self.splitter.pop())
+    }
+
+    /// Like `SyncSplitter::pop_two`, but also returns `None` if the quota doesn't allow it.
+    #[inline]
+    pub fn pop_two(&self) -> Option<((&'s mut T, &'s mut T), usize)> {
+        self.charge(2, || self.splitter.pop_two())
+    }
+
+    /// Like `SyncSplitter::pop_n`, but also returns `None` if the quota doesn't allow it.
+    #[inline]
+    pub fn pop_n(&self, len: usize) -> Option<(&'s mut [T], usize)> {
+        self.charge(len, || self.splitter.pop_n(len))
+    }
+
+    fn charge<R, F: FnOnce() -> Option<R>>(&self, len: usize, pop: F) -> Option<R> {
+        self.used.bump(self.limit, len)?;
+        let popped = pop();
+        if popped.is_none() {
+            self.used.release(len);
+        }
+        popped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn quota_limits_pops() {
+        let mut buffer = [0u32; 10];
+        let splitter = SyncSplitter::new(&mut buffer);
+
+        let first = splitter.with_quota(3);
+        let second = splitter.with_quota(100);
+        assert_eq!(first.pop_two().map(|(_, index)| index), Some(0));
+        assert!(first.pop_two().is_none());
+        assert_eq!(first.pop().map(|(_, index)| index), Some(2));
+        assert!(first.pop().is_none());
+        assert_eq!((first.used(), first.remaining()), (3, 0));
+
+        assert!(second.pop_n(8).is_none());
+        assert_eq!(second.used(), 0);
+        assert_eq!(second.pop_n(7).map(|(_, index)| index), Some(3));
+        assert_eq!(second.remaining(), 93);
+        assert_eq!(splitter.done(), 10);
+    }
+
+    #[test]
+    fn quota_is_shared_between_threads() {
+        let mut buffer = vec![0u32; 1000];
+        let splitter = SyncSplitter::new(&mut buffer);
+        {
+            let group = splitter.with_quota(100);
+            ::std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| while group.pop().is_some() {});
+                }
+            });
+            assert_eq!(group.used(), 100);
+        }
+        assert_eq!(splitter.done(), 100);
+    }
+}
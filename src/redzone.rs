@@ -0,0 +1,180 @@
+//! Guard elements between pops, which catch writes past the end of a popped slice in debug builds.
+
+use alloc::vec::Vec;
+use core::mem;
+
+use cursor::Cursor;
+use SyncSplitter;
+
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
+impl<'a, T: 'a + Sync + Clone + PartialEq> SyncSplitter<'a, T> {
+    /// Wraps the splitter in one which, in debug builds, follows every pop with a guard element set
+    /// to `canary`, and checks that every guard still holds `canary` in `Redzones::done`.
+    ///
+    /// This turns writes past the end of a popped slice (e.g. an off-by-one in the code filling
+    /// it) into a panic which names the overrun pop, instead of silently corrupting the next one.
+    /// For byte splitters, the canary is a poison byte, like `0xa5`.
+    ///
+    /// The guards take up space in the slice, so pops run out sooner and indices differ from an
+    /// unguarded build, and `done` counts the guards too. In release builds, the wrapper pops
+    /// straight from the splitter.
+    pub fn with_redzones(mut self, canary: T) -> Redzones<'a, T> {
+        let start = *self.next.get_mut();
+        let guards = if cfg!(debug_assertions) {
+            let words = self.len.div_ceil(WORD_BITS);
+            Some((0..words).map(|_| Cursor::new(0)).collect())
+        } else {
+            None
+        };
+        Redzones {
+            splitter: self,
+            canary,
+            start,
+            guards,
+        }
+    }
+}
+
+/// A splitter which guards every pop with a canary element in debug builds. See
+/// `SyncSplitter::with_redzones`.
+pub struct Redzones<'a, T: 'a + Sync> {
+    splitter: SyncSplitter<'a, T>,
+    canary: T,
+    start: usize,
+    /// A bit per element of the slice, set for the guards. `None` in release builds.
+    guards: Option<Vec<Cursor>>,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync + Clone + PartialEq> Redzones<'a, T> {
+    /// Like `SyncSplitter::pop`, but followed by a guard in debug builds.
+    #[inline]
+    pub fn pop(&self) -> Option<(&mut T, usize)> {
+        self.pop_n(1).map(|(elements, index)| (&mut elements[0], index))
+    }
+
+    /// Like `SyncSplitter::pop_two`, but followed by a guard in debug builds.
+    #[inline]
+    pub fn pop_two(&self) -> Option<((&mut T, &mut T), usize)> {
+        self.pop_n(2).map(|(elements, index)| {
+            let (first, second) = elements.split_at_mut(1);
+            ((&mut first[0], &mut second[0]), index)
+        })
+    }
+
+    /// Like `SyncSplitter::pop_n`, but followed by a guard in debug builds.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice for the popped
+    /// elements and their guard.
+    pub fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
+        let guards = match self.guards {
+            Some(ref guards) => guards,
+            None => return self.splitter.pop_n(len),
+        };
+        let (elements, index) = self.splitter.pop_n(len.checked_add(1)?)?;
+        let (elements, guard) = elements.split_at_mut(len);
+        guard[0] = self.canary.clone();
+        let guard = index - self.splitter.offset + len;
+        let bit = 1 << (guard % WORD_BITS);
+        guards[guard / WORD_BITS].update(|word| Some(((), word | bit)));
+        Some((elements, index))
+    }
+
+    /// Consumes the splitter and returns the total number of popped elements, guards included.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// In debug builds, if a guard doesn't hold the canary anymore, i.e. if something wrote past
+    /// the end of a popped slice.
+    pub fn done(self) -> usize {
+        let Redzones {
+            splitter,
+            canary,
+            start,
+            guards,
+        } = self;
+        let (data, offset) = (splitter.data, splitter.offset);
+        let popped = splitter.done();
+        let guards = match guards {
+            Some(guards) => guards,
+            None => return popped,
+        };
+        let mut claim_start = start;
+        for index in start..popped {
+            if guards[index / WORD_BITS].load() & (1 << (index % WORD_BITS)) == 0 {
+                continue;
+            }
+            if unsafe { *data.add(index) != canary } {
+                panic!(
+                    "write past the end of the elements popped at {}..{}",
+                    offset + claim_start,
+                    offset + index
+                );
+            }
+            claim_start = index + 1;
+        }
+        popped
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn guards_follow_every_pop() {
+        let mut buffer = [0u8; 10];
+        {
+            let splitter = SyncSplitter::new(&mut buffer).with_redzones(0xa5);
+            let (first, index) = splitter.pop().unwrap();
+            *first = 1;
+            assert_eq!(index, 0);
+            let ((second, third), index) = splitter.pop_two().unwrap();
+            *second = 2;
+            *third = 3;
+            assert_eq!(index, 2);
+            let (rest, index) = splitter.pop_n(3).unwrap();
+            rest.copy_from_slice(&[4, 5, 6]);
+            assert_eq!(index, 5);
+            assert!(splitter.pop_n(1).is_none());
+            assert_eq!(splitter.done(), 9);
+        }
+        assert_eq!(buffer, [1, 0xa5, 2, 3, 0xa5, 4, 5, 6, 0xa5, 0]);
+    }
+
+    #[test]
+    fn guards_survive_threads() {
+        let mut buffer = vec![0usize; 1000];
+        let splitter = SyncSplitter::new(&mut buffer).with_redzones(usize::MAX);
+        ::std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let splitter = &splitter;
+                scope.spawn(move || {
+                    while let Some((elements, index)) = splitter.pop_n(thread) {
+                        for (position, element) in elements.iter_mut().enumerate() {
+                            *element = index + position;
+                        }
+                    }
+                });
+            }
+        });
+        assert!(splitter.done() > 990);
+    }
+
+    #[test]
+    #[should_panic(expected = "write past the end of the elements popped at 2..4")]
+    fn overruns_panic_at_done() {
+        let mut buffer = [0u32; 8];
+        let splitter = SyncSplitter::new(&mut buffer).with_redzones(u32::MAX);
+        splitter.pop();
+        let (elements, index) = splitter.pop_n(2).unwrap();
+        elements.copy_from_slice(&[1, 2]);
+        // An off-by-one, through a pointer to the whole slice (as unsafe code filling the
+        // elements might use), overwrites the guard.
+        unsafe { *splitter.splitter.data.add(index + 2) = 7 };
+        splitter.pop();
+        splitter.done();
+    }
+}
@@ -0,0 +1,172 @@
+//! A region at the end of a splitter's slice which only critical pops can use.
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Creates a new `SyncSplitter` from a slice, keeping its last `reserved` elements for
+    /// `pop_reserved`.
+    ///
+    /// Every other pop only sees the rest of the slice, so critical allocations (error or
+    /// placeholder nodes, root records) still succeed once ordinary pops have exhausted it. `done`
+    /// and the other statistics only cover the ordinary part: reserved elements are popped from
+    /// the start of the reserved region, and `reserved_popped` says how many were.
+    ///
+    /// The reserved region is at the end of the slice, so truncating the arena to the length
+    /// returned by `done` would throw the reserved elements away: use `done_with_reserved` to get
+    /// a length which keeps them.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `reserved > slice.len()`, or `slice.len() > isize::MAX`.
+    pub fn with_reserved(slice: &'a mut [T], reserved: usize) -> Self {
+        assert!(
+            reserved <= slice.len(),
+            "can't reserve {} elements of a slice of {}",
+            reserved,
+            slice.len()
+        );
+        let mut splitter = SyncSplitter::new(slice);
+        splitter.len -= reserved;
+        splitter.reserved = reserved;
+        splitter
+    }
+
+    /// Pops one mutable reference off the reserved region and returns it.
+    ///
+    /// Also returns the element's index in the original slice.
+    ///
+    /// Returns `None` if the reserved region was exhausted. This doesn't depend on the ordinary
+    /// pops, and doesn't trigger the exhaustion policy.
+    #[inline]
+    pub fn pop_reserved(&self) -> Option<(&mut T, usize)> {
+        self.next_reserved.bump(self.reserved, 1).map(|index| {
+            let index = self.len + index;
+            (unsafe { &mut *self.data.add(index) }, self.offset + index)
+        })
+    }
+
+    /// Returns the number of elements popped from the reserved region so far.
+    #[inline]
+    pub fn reserved_popped(&self) -> usize {
+        self.next_reserved.load()
+    }
+
+    /// Consumes the splitter and returns the length of the prefix of its slice which holds every
+    /// popped element, reserved ones included.
+    ///
+    /// This is what `done` returns if no reserved element was popped. Otherwise, it's the length
+    /// of the ordinary part plus the number of reserved pops, so the prefix also holds the
+    /// ordinary elements which were never popped.
+    #[inline]
+    pub fn done_with_reserved(self) -> usize {
+        match self.next_reserved.load() {
+            0 => self.next.load(),
+            reserved_popped => self.len + reserved_popped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn reserved_pops_outlive_ordinary_ones() {
+        let mut buffer = [0u32; 6];
+        {
+            let splitter = SyncSplitter::with_reserved(&mut buffer, 2);
+            let (popped, _) = splitter.pop_n(4).unwrap();
+            popped.copy_from_slice(&[1, 2, 3, 4]);
+            assert!(splitter.pop().is_none());
+            assert_eq!(splitter.bytes_remaining(), 0);
+
+            let (reserved, index) = splitter.pop_reserved().unwrap();
+            *reserved = 5;
+            assert_eq!(index, 4);
+            assert_eq!(splitter.reserved_popped(), 1);
+            assert_eq!(splitter.done(), 4);
+        }
+        assert_eq!(buffer, [1, 2, 3, 4, 5, 0]);
+
+        let splitter = SyncSplitter::with_reserved(&mut buffer, 1);
+        assert!(splitter.pop_reserved().is_some());
+        assert!(splitter.pop_reserved().is_none());
+        assert_eq!(splitter.pop_n(5).map(|(_, index)| index), Some(0));
+    }
+
+    #[test]
+    fn truncating_to_done_with_reserved_keeps_reserved_pops() {
+        let mut arena = vec![0u32; 10];
+        let len = {
+            let splitter = SyncSplitter::with_reserved(&mut arena, 3);
+            let ((first, _), _) = splitter.pop_two().unwrap();
+            *first = 1;
+            *splitter.pop_reserved().unwrap().0 = 2;
+            splitter.done_with_reserved()
+        };
+        arena.truncate(len);
+        assert_eq!(arena, [1, 0, 0, 0, 0, 0, 0, 2]);
+
+        let mut arena = vec![0u32; 10];
+        let len = {
+            let splitter = SyncSplitter::with_reserved(&mut arena, 3);
+            splitter.pop_two().unwrap();
+            splitter.done_with_reserved()
+        };
+        assert_eq!(len, 2);
+    }
+
+    #[test]
+    fn reserved_pops_race() {
+        let mut buffer = vec![usize::MAX; 100];
+        let splitter = SyncSplitter::with_reserved(&mut buffer, 10);
+        let mut indices = ::std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| {
+                    let splitter = &splitter;
+                    scope.spawn(move || {
+                        let mut indices = Vec::new();
+                        while splitter.pop().is_some() {}
+                        while let Some((_, index)) = splitter.pop_reserved() {
+                            indices.push(index);
+                        }
+                        indices
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        indices.sort();
+        assert_eq!(indices, (90..100).collect::<Vec<_>>());
+        assert_eq!(splitter.done(), 90);
+    }
+
+    #[test]
+    fn reset_empties_the_reserved_region() {
+        let mut buffer = [0u32; 4];
+        let mut splitter = SyncSplitter::with_reserved(&mut buffer, 1);
+        for _ in 0..3 {
+            assert_eq!(splitter.pop_reserved().map(|(_, index)| index), Some(3));
+            assert!(splitter.pop_reserved().is_none());
+            {
+                let frame = splitter.begin_frame();
+                assert!(frame.pop_n(3).is_some());
+                frame.rewind();
+            }
+            assert_eq!(splitter.reserved_popped(), 1);
+            splitter.reset();
+            assert_eq!(splitter.reserved_popped(), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_reserving_more_than_the_slice() {
+        SyncSplitter::with_reserved(&mut [0u32; 2], 3);
+    }
+}
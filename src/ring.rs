@@ -0,0 +1,409 @@
+//! A splitter over a ring buffer, whose slots are popped again once they're released.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "async")]
+use core::future::Future;
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+
+use cursor::Cursor;
+#[cfg(feature = "std")]
+use wait::Waiters;
+
+/// A `SyncRingSplitter` allows multiple threads to pop slots from a ring buffer at the same time,
+/// reusing slots once they're released.
+///
+/// Every popped slot is a `RingSlot` guard, which releases the slot when dropped. Producers pop
+/// slots and fill them in, then hand the guards over to consumers (the guards are `Send`), which
+/// release the slots once they're done with them. This makes a bounded, zero-copy transport for
+/// fixed-size records.
+///
+/// Slots are popped in order around the ring. Released slots are only reused once every slot popped
+/// before them was released too, so a slot which is held for a long time stalls the ring once it
+/// wraps around to it.
+///
+/// Once the ring is closed with `close`, no more slots are popped. With the `std` feature,
+/// `pop_or_wait` blocks until a slot is released instead of failing when the ring is full, and with
+/// the `async` feature, `pop_async` returns a future which resolves once one is.
+pub struct SyncRingSplitter<'a, T: 'a + Send> {
+    data: *mut T,
+    len: usize,
+    /// The number of slots popped so far.
+    head: Cursor,
+    /// The number of slots released in order so far, which is at most `head`.
+    tail: Cursor,
+    /// For each slot, one more than the sequence number of the last pop of it which was released.
+    released: Vec<Cursor>,
+    /// One if the ring was closed, zero otherwise.
+    closed: Cursor,
+    #[cfg(feature = "std")]
+    waiters: Waiters,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: 'a + Send> SyncRingSplitter<'a, T> {
+    /// Creates a new `SyncRingSplitter` whose slots are the elements of `slice`.
+    pub fn new(slice: &'a mut [T]) -> Self {
+        SyncRingSplitter {
+            data: slice.as_mut_ptr(),
+            len: slice.len(),
+            head: Cursor::new(0),
+            tail: Cursor::new(0),
+            released: (0..slice.len()).map(|_| Cursor::new(0)).collect(),
+            closed: Cursor::new(0),
+            #[cfg(feature = "std")]
+            waiters: Waiters::new(),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the number of slots in the ring.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the ring has no slots at all.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of slots which can't be popped right now, because they weren't released
+    /// yet (or because an earlier slot wasn't).
+    #[inline]
+    pub fn in_flight(&self) -> usize {
+        let tail = self.tail.load();
+        self.head.load() - tail
+    }
+
+    /// Closes the ring: every later pop fails and threads blocked in `pop_or_wait` return `None`.
+    ///
+    /// Slots which were already popped stay valid and are released as usual.
+    pub fn close(&self) {
+        self.closed.update(|_| Some(((), 1)));
+        #[cfg(feature = "std")]
+        self.waiters.notify();
+    }
+
+    /// Returns `true` if the ring was closed.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.closed.load() != 0
+    }
+
+    /// Pops the next slot around the ring.
+    ///
+    /// Returns `None` if the ring is full, i.e. if the next slot wasn't released since it was last
+    /// popped, or if it was closed. Unlike with `SyncSplitter`, later pops may succeed again once
+    /// slots are released.
+    #[inline]
+    pub fn pop(&self) -> Option<RingSlot<'_, 'a, T>> {
+        if self.is_closed() {
+            return None;
+        }
+        self.head
+            .update(|head| {
+                // If `head` is already stale, `tail` may be past it. That wraps around to a huge
+                // value, and the update is retried with a fresh `head` anyway.
+                let in_flight = head.wrapping_sub(self.tail.load());
+                if in_flight < self.len || in_flight > isize::MAX as usize {
+                    Some((head, head.wrapping_add(1)))
+                } else {
+                    None
+                }
+            })
+            .map(|sequence| RingSlot {
+                ring: self,
+                sequence,
+            })
+    }
+
+    /// Pops the next slot around the ring, blocking until one is released if the ring is full.
+    ///
+    /// Only available with the `std` feature.
+    ///
+    /// Returns `None` if the ring was closed, or is closed while waiting. Note that a ring with no
+    /// slots at all blocks until it's closed.
+    #[cfg(feature = "std")]
+    pub fn pop_or_wait(&self) -> Option<RingSlot<'_, 'a, T>> {
+        self.waiters.wait_for(|| match self.pop() {
+            Some(slot) => Some(Some(slot)),
+            None if self.is_closed() => Some(None),
+            None => None,
+        })
+    }
+
+    /// Returns a future which pops the next slot around the ring, resolving once one is released if
+    /// the ring is full. This lets async pipelines apply backpressure without blocking a thread.
+    ///
+    /// Only available with the `async` feature.
+    ///
+    /// The future resolves to `None` if the ring was closed, or is closed while waiting.
+    #[cfg(feature = "async")]
+    pub fn pop_async(&self) -> PopAsync<'_, 'a, T> {
+        PopAsync { ring: self }
+    }
+
+    /// Marks the slot popped with `sequence` as released, then moves the tail past every slot
+    /// which was released in order.
+    fn release(&self, sequence: usize) {
+        self.released[sequence % self.len].update(|_| Some(((), sequence.wrapping_add(1))));
+        loop {
+            let tail = self.tail.load();
+            if self.released[tail % self.len].load() != tail.wrapping_add(1) {
+                break;
+            }
+            // If another thread moved the tail first, look at the slot after it instead.
+            self.tail.update(|current| {
+                if current == tail {
+                    Some(((), tail.wrapping_add(1)))
+                } else {
+                    None
+                }
+            });
+        }
+        #[cfg(feature = "std")]
+        self.waiters.notify();
+    }
+}
+
+unsafe impl<'a, T: Send> Sync for SyncRingSplitter<'a, T> {}
+unsafe impl<'a, T: Send> Send for SyncRingSplitter<'a, T> {}
+
+/// A slot popped from a `SyncRingSplitter`, released when dropped.
+///
+/// Dereferences to the slot's element.
+pub struct RingSlot<'r, 'a: 'r, T: 'a + Send> {
+    ring: &'r SyncRingSplitter<'a, T>,
+    sequence: usize,
+}
+
+impl<'r, 'a: 'r, T: 'a + Send> RingSlot<'r, 'a, T> {
+    /// Returns the slot's index in the ring's slice.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.sequence % self.ring.len
+    }
+
+    /// Returns the number of slots popped from the ring before this one, which orders slots
+    /// across wrap-arounds.
+    #[inline]
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// Releases the slot, like dropping it.
+    #[inline]
+    pub fn release(self) {}
+}
+
+impl<'r, 'a: 'r, T: 'a + Send> Deref for RingSlot<'r, 'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // Until it's released, the slot can't be popped again.
+        unsafe { &*self.ring.data.add(self.index()) }
+    }
+}
+
+impl<'r, 'a: 'r, T: 'a + Send> DerefMut for RingSlot<'r, 'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ring.data.add(self.index()) }
+    }
+}
+
+impl<'r, 'a: 'r, T: 'a + Send> Drop for RingSlot<'r, 'a, T> {
+    fn drop(&mut self) {
+        self.ring.release(self.sequence);
+    }
+}
+
+unsafe impl<'r, 'a: 'r, T: 'a + Send> Send for RingSlot<'r, 'a, T> {}
+unsafe impl<'r, 'a: 'r, T: 'a + Send + Sync> Sync for RingSlot<'r, 'a, T> {}
+
+/// The future returned by `SyncRingSplitter::pop_async`.
+#[cfg(feature = "async")]
+pub struct PopAsync<'r, 'a: 'r, T: 'a + Send> {
+    ring: &'r SyncRingSplitter<'a, T>,
+}
+
+#[cfg(feature = "async")]
+impl<'r, 'a: 'r, T: 'a + Send> Future for PopAsync<'r, 'a, T> {
+    type Output = Option<RingSlot<'r, 'a, T>>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let ring = self.ring;
+        ring.waiters.poll_for(context, || match ring.pop() {
+            Some(slot) => Some(Some(slot)),
+            None if ring.is_closed() => Some(None),
+            None => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use super::{RingSlot, SyncRingSplitter};
+
+    #[test]
+    fn slots_are_reused_in_order() {
+        let mut buffer = [0u32; 3];
+        let ring = SyncRingSplitter::new(&mut buffer);
+        let mut first = ring.pop().unwrap();
+        let second = ring.pop().unwrap();
+        let third = ring.pop().unwrap();
+        assert!(ring.pop().is_none());
+        assert_eq!((first.index(), second.index(), third.index()), (0, 1, 2));
+        *first = 7;
+
+        // Releasing out of order doesn't free anything until the oldest slot is released.
+        second.release();
+        assert!(ring.pop().is_none());
+        assert_eq!(ring.in_flight(), 3);
+        first.release();
+        assert_eq!(ring.in_flight(), 1);
+
+        let fourth = ring.pop().unwrap();
+        let fifth = ring.pop().unwrap();
+        assert!(ring.pop().is_none());
+        assert_eq!((*fourth, fourth.index(), fourth.sequence()), (7, 0, 3));
+        assert_eq!(fifth.index(), 1);
+        drop((third, fourth, fifth));
+        assert_eq!(ring.in_flight(), 0);
+
+        let mut empty: [u32; 0] = [];
+        assert!(SyncRingSplitter::new(&mut empty).pop().is_none());
+    }
+
+    #[test]
+    fn transports_records_between_threads() {
+        const RECORDS: usize = 10_000;
+        let mut buffer = [0usize; 16];
+        let ring = SyncRingSplitter::new(&mut buffer);
+        let (sender, receiver) = mpsc::channel();
+        let sum = ::std::thread::scope(|scope| {
+            let consumer = scope.spawn(move || {
+                receiver.iter().map(|slot: RingSlot<usize>| *slot).sum::<usize>()
+            });
+            for producer in 0..2 {
+                let (ring, sender) = (&ring, sender.clone());
+                scope.spawn(move || {
+                    for record in (producer..RECORDS).step_by(2) {
+                        let mut slot = loop {
+                            if let Some(slot) = ring.pop() {
+                                break slot;
+                            }
+                            ::std::thread::yield_now();
+                        };
+                        *slot = record;
+                        sender.send(slot).ok().unwrap();
+                    }
+                });
+            }
+            drop(sender);
+            consumer.join().unwrap()
+        });
+        assert_eq!(sum, (0..RECORDS).sum::<usize>());
+        assert_eq!(ring.in_flight(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pop_or_wait_blocks_until_release_or_close() {
+        const RECORDS: usize = 10_000;
+        let mut buffer = [0usize; 4];
+        let ring = SyncRingSplitter::new(&mut buffer);
+        let (sender, receiver) = mpsc::channel();
+        let sum = ::std::thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                for record in 0..RECORDS {
+                    let mut slot = ring.pop_or_wait().unwrap();
+                    *slot = record;
+                    sender.send(slot).ok().unwrap();
+                }
+                // The ring is full now, until it's closed.
+                let held = (0..4).map(|_| ring.pop_or_wait().unwrap()).collect::<Vec<_>>();
+                assert!(ring.pop_or_wait().is_none());
+                drop(held);
+            });
+            let sum = receiver
+                .iter()
+                .take(RECORDS)
+                .map(|slot: RingSlot<usize>| *slot)
+                .sum::<usize>();
+            while ring.in_flight() < 4 {
+                ::std::thread::yield_now();
+            }
+            ring.close();
+            producer.join().unwrap();
+            sum
+        });
+        assert_eq!(sum, (0..RECORDS).sum::<usize>());
+        assert!(ring.pop().is_none());
+    }
+
+    #[cfg(feature = "async")]
+    fn block_on<F: ::std::future::Future>(future: F) -> F::Output {
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+        use std::thread::{self, Thread};
+
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(Unpark(thread::current())).into();
+        let mut context = Context::from_waker(&waker);
+        let mut future = ::std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn pop_async_resolves_on_release_or_close() {
+        const RECORDS: usize = 10_000;
+        let mut buffer = [0usize; 4];
+        let ring = SyncRingSplitter::new(&mut buffer);
+        let (sender, receiver) = mpsc::channel();
+        let sum = ::std::thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                for record in 0..RECORDS {
+                    let mut slot = block_on(ring.pop_async()).unwrap();
+                    *slot = record;
+                    sender.send(slot).ok().unwrap();
+                }
+                let held = [(); 4].map(|_| block_on(ring.pop_async()).unwrap());
+                assert!(block_on(ring.pop_async()).is_none());
+                drop(held);
+            });
+            let sum = receiver
+                .iter()
+                .take(RECORDS)
+                .map(|slot: RingSlot<usize>| *slot)
+                .sum::<usize>();
+            while ring.in_flight() < 4 {
+                ::std::thread::yield_now();
+            }
+            ring.close();
+            producer.join().unwrap();
+            sum
+        });
+        assert_eq!(sum, (0..RECORDS).sum::<usize>());
+    }
+}
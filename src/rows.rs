@@ -0,0 +1,229 @@
+//! Splitting two dimensional buffers (like images) into bands of rows.
+
+use core::marker::PhantomData;
+use core::slice;
+
+use cursor::Cursor;
+
+/// A `RowSplitter` allows multiple threads to claim bands of consecutive rows of a two dimensional
+/// buffer at the same time.
+///
+/// Rows are `width` elements long, but consecutive rows start `pitch` elements apart, as is common
+/// for buffers produced by GPUs and aligned allocators. The `pitch - width` padding elements at the
+/// end of each row are never handed out.
+pub struct RowSplitter<'a, T: 'a + Sync> {
+    data: *mut T,
+    width: usize,
+    pitch: usize,
+    num_rows: usize,
+    next: Cursor,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> RowSplitter<'a, T> {
+    /// Creates a new `RowSplitter` from a buffer with rows of the given width and pitch.
+    ///
+    /// The number of rows is the largest one which fits in `buffer`; the padding of the last row is
+    /// not required to be present.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `width > pitch` or if `pitch` is zero.
+    pub fn new(buffer: &'a mut [T], width: usize, pitch: usize) -> Self {
+        assert!(pitch > 0, "pitch must be non-zero");
+        assert!(width <= pitch, "width {} larger than pitch {}", width, pitch);
+        let num_rows = if buffer.len() >= width {
+            (buffer.len() - width) / pitch + 1
+        } else {
+            0
+        };
+        RowSplitter {
+            data: buffer.as_mut_ptr(),
+            width,
+            pitch,
+            num_rows,
+            next: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the width of a row, in elements.
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the distance between the starts of consecutive rows, in elements.
+    #[inline]
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Returns the total number of rows in the buffer.
+    #[inline]
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Pops a band of `num_rows` consecutive rows and returns a strided mutable view over it.
+    ///
+    /// Also returns the index of the band's first row.
+    ///
+    /// Returns `None` if not enough rows were left in the underlying buffer.
+    #[inline]
+    pub fn pop_rows(&self, num_rows: usize) -> Option<(Rows<'_, T>, usize)> {
+        self.next.bump(self.num_rows, num_rows).map(|row| {
+            (
+                Rows {
+                    data: unsafe { self.data.add(row * self.pitch) },
+                    width: self.width,
+                    pitch: self.pitch,
+                    num_rows,
+                    dummy: PhantomData,
+                },
+                row,
+            )
+        })
+    }
+
+    /// Pops a single row and returns it.
+    ///
+    /// Also returns the row's index.
+    ///
+    /// Returns `None` if all rows were popped.
+    #[inline]
+    pub fn pop_row(&self) -> Option<(&mut [T], usize)> {
+        self.next.bump(self.num_rows, 1).map(|row| {
+            (
+                unsafe { slice::from_raw_parts_mut(self.data.add(row * self.pitch), self.width) },
+                row,
+            )
+        })
+    }
+
+    /// Consumes the splitter and returns the total number of popped rows.
+    #[inline]
+    pub fn done(self) -> usize {
+        self.next.load()
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for RowSplitter<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for RowSplitter<'a, T> {}
+
+/// A mutable view over a band of consecutive rows, popped from a `RowSplitter`.
+pub struct Rows<'a, T: 'a> {
+    data: *mut T,
+    width: usize,
+    pitch: usize,
+    num_rows: usize,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: 'a> Rows<'a, T> {
+    /// Returns the number of rows in the band.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Returns `true` if the band has no rows.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.num_rows == 0
+    }
+
+    /// Returns the width of each row, in elements.
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the row at `index` in the band.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= self.len()`.
+    #[inline]
+    pub fn row(&self, index: usize) -> &[T] {
+        assert!(index < self.num_rows, "row {} out of {}", index, self.num_rows);
+        unsafe { slice::from_raw_parts(self.data.add(index * self.pitch), self.width) }
+    }
+
+    /// Returns the row at `index` in the band, mutably.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= self.len()`.
+    #[inline]
+    pub fn row_mut(&mut self, index: usize) -> &mut [T] {
+        assert!(index < self.num_rows, "row {} out of {}", index, self.num_rows);
+        unsafe { slice::from_raw_parts_mut(self.data.add(index * self.pitch), self.width) }
+    }
+
+    /// Returns an iterator over the rows in the band, mutably.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut [T]> + '_ {
+        let (data, width, pitch) = (self.data, self.width, self.pitch);
+        (0..self.num_rows).map(move |index| unsafe {
+            // Rows don't overlap since `width <= pitch`.
+            slice::from_raw_parts_mut(data.add(index * pitch), width)
+        })
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for Rows<'a, T> {}
+unsafe impl<'a, T: Send> Send for Rows<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::RowSplitter;
+
+    #[test]
+    fn pops_rows_without_padding() {
+        // Three rows of width 2 and pitch 3, without padding after the last row.
+        let mut buffer = [0u32; 8];
+        {
+            let splitter = RowSplitter::new(&mut buffer, 2, 3);
+            assert_eq!(splitter.num_rows(), 3);
+            {
+                let (mut rows, first_row) = splitter.pop_rows(2).unwrap();
+                assert_eq!(first_row, 0);
+                assert_eq!(rows.len(), 2);
+                for (index, row) in rows.iter_mut().enumerate() {
+                    assert_eq!(row.len(), 2);
+                    row[0] = index as u32 + 1;
+                    row[1] = index as u32 + 1;
+                }
+                rows.row_mut(1)[1] = 5;
+                assert_eq!(rows.row(1), &[2, 5]);
+
+                assert!(splitter.pop_rows(2).is_none());
+                let (row, index) = splitter.pop_row().unwrap();
+                assert_eq!(index, 2);
+                row.copy_from_slice(&[3, 3]);
+                assert!(splitter.pop_row().is_none());
+            }
+            assert_eq!(splitter.done(), 3);
+        }
+        assert_eq!(buffer, [1, 1, 0, 2, 5, 0, 3, 3]);
+    }
+
+    #[test]
+    fn counts_rows_in_small_buffers() {
+        let mut buffer = [0u8; 3];
+        assert_eq!(RowSplitter::new(&mut buffer[..], 4, 4).num_rows(), 0);
+        assert_eq!(RowSplitter::new(&mut buffer[..], 3, 8).num_rows(), 1);
+        assert_eq!(RowSplitter::new(&mut buffer[..], 0, 1).num_rows(), 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn width_larger_than_pitch_panics() {
+        let mut buffer = [0u8; 16];
+        let _splitter = RowSplitter::new(&mut buffer, 5, 4);
+    }
+}
@@ -0,0 +1,419 @@
+//! Saving and loading built arenas with serde. Available with the `serde` feature.
+
+use alloc::string::String;
+use core::fmt;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde::Deserialize;
+
+use SyncSplitter;
+
+/// The popped prefix of an arena, along with the index of its root, which serializes as a struct
+/// with `len`, `root` and `elements` fields.
+///
+/// Arenas are usually much larger than what's popped from them, so checkpointing the whole slice
+/// would also write out its untouched tail. Wrap the arena and the length returned by `done`
+/// instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsedArena<'a, T: 'a> {
+    elements: &'a [T],
+    root: Option<usize>,
+}
+
+impl<'a, T: 'a> UsedArena<'a, T> {
+    /// Wraps the first `len` elements of `arena`, typically the number returned by `done`, with
+    /// the index of the root element, if any.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `len > arena.len()`, or if `root` isn't less than `len`.
+    pub fn new(arena: &'a [T], len: usize, root: Option<usize>) -> Self {
+        assert!(len <= arena.len(), "used length {} out of {}", len, arena.len());
+        assert!(
+            root.is_none_or(|root| root < len),
+            "root {:?} out of {} used elements",
+            root,
+            len
+        );
+        UsedArena {
+            elements: &arena[..len],
+            root,
+        }
+    }
+
+    /// Returns the used elements.
+    #[inline]
+    pub fn elements(&self) -> &'a [T] {
+        self.elements
+    }
+
+    /// Returns the number of used elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns `true` if no elements were used.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns the index of the root element, if any.
+    #[inline]
+    pub fn root(&self) -> Option<usize> {
+        self.root
+    }
+}
+
+impl<'a, T: 'a + Serialize> Serialize for UsedArena<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("UsedArena", 3)?;
+        state.serialize_field("len", &self.elements.len())?;
+        state.serialize_field("root", &self.root)?;
+        state.serialize_field("elements", self.elements)?;
+        state.end()
+    }
+}
+
+/// Deserializes a sequence of elements straight into a splitter's slice, popping elements as they
+/// are decoded, with no intermediate `Vec`.
+///
+/// The value is the index of the first element in the original slice and the number of
+/// elements. They're consecutive if the format gives the sequence's length up front (then they're
+/// popped together), or if no other thread pops from the splitter at the same time.
+///
+/// Fails if the splitter runs out of elements.
+pub struct ArenaSeed<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s SyncSplitter<'a, T>,
+}
+
+impl<'s, 'a: 's, T: 'a + Sync> ArenaSeed<'s, 'a, T> {
+    /// Creates a seed which pops elements from `splitter`.
+    #[inline]
+    pub fn new(splitter: &'s SyncSplitter<'a, T>) -> Self {
+        ArenaSeed { splitter }
+    }
+}
+
+impl<'de, 's, 'a: 's, T: 'a + Sync + Deserialize<'de>> DeserializeSeed<'de>
+    for ArenaSeed<'s, 'a, T>
+{
+    type Value = (usize, usize);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 's, 'a: 's, T: 'a + Sync + Deserialize<'de>> Visitor<'de> for ArenaSeed<'s, 'a, T> {
+    type Value = (usize, usize);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of arena elements")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        if let Some(len) = seq.size_hint() {
+            let (elements, start) = pop_or_fail::<T, A::Error>(self.splitter, len)?;
+            fill(elements, &mut seq)?;
+            return Ok((start, len));
+        }
+        let (mut start, mut len) = (None, 0);
+        while let Some(value) = seq.next_element()? {
+            let (element, index) = pop_or_fail::<T, A::Error>(self.splitter, 1)?;
+            element[0] = value;
+            start.get_or_insert(index);
+            len += 1;
+        }
+        Ok((start.unwrap_or(self.splitter.offset + self.splitter.next.load()), len))
+    }
+}
+
+/// Deserializes a `UsedArena` straight into a splitter's slice, popping all of its elements
+/// together before they're decoded.
+///
+/// The value is the index of the chunk's first element in the original slice, its number of
+/// elements and the index of its root, if any, offset by the first one. Since each chunk is popped
+/// in one go, several saved chunks can be loaded into the same splitter by different threads at
+/// the same time; the indices stored inside each chunk can then be rebased (see
+/// `RebaseIndices`).
+///
+/// Fails if the splitter runs out of elements, if the number of elements doesn't match the saved
+/// length, which must come before them, or if the root isn't less than the saved length. The
+/// elements of a chunk which fails to load are given back to the splitter, unless another thread
+/// popped from it in the meantime (then they stay popped, but unused).
+pub struct UsedArenaSeed<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s SyncSplitter<'a, T>,
+}
+
+impl<'s, 'a: 's, T: 'a + Sync> UsedArenaSeed<'s, 'a, T> {
+    /// Creates a seed which pops elements from `splitter`.
+    #[inline]
+    pub fn new(splitter: &'s SyncSplitter<'a, T>) -> Self {
+        UsedArenaSeed { splitter }
+    }
+}
+
+const FIELDS: &[&str] = &["len", "root", "elements"];
+
+impl<'de, 's, 'a: 's, T: 'a + Sync + Deserialize<'de>> DeserializeSeed<'de>
+    for UsedArenaSeed<'s, 'a, T>
+{
+    type Value = (usize, usize, Option<usize>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("UsedArena", FIELDS, self)
+    }
+}
+
+impl<'de, 's, 'a: 's, T: 'a + Sync + Deserialize<'de>> Visitor<'de> for UsedArenaSeed<'s, 'a, T> {
+    type Value = (usize, usize, Option<usize>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a used arena")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let len = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let root = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        check_root(root, len)?;
+        let (elements, start) = pop_or_fail::<T, A::Error>(self.splitter, len)?;
+        let filled = seq.next_element_seed(FillSeed(elements)).and_then(|filled| {
+            filled.ok_or_else(|| de::Error::invalid_length(2, &self))
+        });
+        self.give_back_on_error(start, len, filled)?;
+        Ok(rebased(start, len, root))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut len, mut root, mut start) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match &key[..] {
+                "len" => len = Some(map.next_value()?),
+                "root" => root = Some(map.next_value()?),
+                "elements" => {
+                    let len = len.ok_or_else(|| de::Error::custom("`len` must come first"))?;
+                    check_root(root.flatten(), len)?;
+                    let (elements, index) = pop_or_fail::<T, A::Error>(self.splitter, len)?;
+                    self.give_back_on_error(index, len, map.next_value_seed(FillSeed(elements)))?;
+                    start = Some(index);
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let len = len.ok_or_else(|| de::Error::missing_field("len"))?;
+        let start = start.ok_or_else(|| de::Error::missing_field("elements"))?;
+        // The root may come after the elements.
+        self.give_back_on_error(start, len, check_root(root.flatten(), len))?;
+        Ok(rebased(start, len, root.flatten()))
+    }
+}
+
+impl<'s, 'a: 's, T: 'a + Sync> UsedArenaSeed<'s, 'a, T> {
+    /// Gives the `len` elements popped at `start` back to the splitter if `result` is an error.
+    fn give_back_on_error<E>(
+        &self,
+        start: usize,
+        len: usize,
+        result: Result<(), E>,
+    ) -> Result<(), E> {
+        if result.is_err() {
+            // The elements were popped here, and the references to them are gone.
+            unsafe { self.splitter.undo_last(start, len) };
+        }
+        result
+    }
+}
+
+/// Checks that a chunk's root is one of its `len` elements, like `UsedArena::new` does.
+fn check_root<E: de::Error>(root: Option<usize>, len: usize) -> Result<(), E> {
+    match root {
+        Some(root) if root >= len => Err(E::invalid_value(
+            de::Unexpected::Unsigned(root as u64),
+            &"a root index less than `len`",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn rebased(start: usize, len: usize, root: Option<usize>) -> (usize, usize, Option<usize>) {
+    (start, len, root.map(|root| start + root))
+}
+
+fn pop_or_fail<'s, T: Sync, E: de::Error>(
+    splitter: &'s SyncSplitter<T>,
+    len: usize,
+) -> Result<(&'s mut [T], usize), E> {
+    splitter
+        .pop_n(len)
+        .ok_or_else(|| E::custom(format_args!("arena too small for {} more elements", len)))
+}
+
+/// Deserializes a sequence into already popped elements, which must all be overwritten.
+struct FillSeed<'e, T: 'e>(&'e mut [T]);
+
+impl<'de, 'e, T: 'e + Deserialize<'de>> DeserializeSeed<'de> for FillSeed<'e, T> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'e, T: 'e + Deserialize<'de>> Visitor<'de> for FillSeed<'e, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a sequence of {} arena elements", self.0.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        fill(self.0, &mut seq)
+    }
+}
+
+fn fill<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(
+    elements: &mut [T],
+    seq: &mut A,
+) -> Result<(), A::Error> {
+    let len = elements.len();
+    for (index, element) in elements.iter_mut().enumerate() {
+        *element = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(index, &Expected(len)))?;
+    }
+    if seq.next_element::<IgnoredAny>()?.is_some() {
+        return Err(de::Error::invalid_length(len + 1, &Expected(len)));
+    }
+    Ok(())
+}
+
+/// The expected number of elements, for errors.
+struct Expected(usize);
+
+impl de::Expected for Expected {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} arena elements", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArenaSeed, UsedArena, UsedArenaSeed};
+    use serde::de::DeserializeSeed;
+    use serde_json::Deserializer;
+    use SyncSplitter;
+
+    #[test]
+    fn serializes_only_the_used_prefix() {
+        let mut arena = vec![(0u8, 0u8); 100];
+        let len = {
+            let splitter = SyncSplitter::new(&mut arena);
+            let ((left, right), _) = splitter.pop_two().unwrap();
+            *left = (1, 2);
+            *right = (3, 4);
+            splitter.done()
+        };
+        let used = UsedArena::new(&arena, len, Some(0));
+        assert_eq!((used.len(), used.root()), (2, Some(0)));
+        assert_eq!(
+            serde_json::to_string(&used).unwrap(),
+            r#"{"len":2,"root":0,"elements":[[1,2],[3,4]]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&UsedArena::new(&arena, 0, None)).unwrap(),
+            r#"{"len":0,"root":null,"elements":[]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn roots_must_be_used() {
+        UsedArena::new(&[1, 2, 3], 2, Some(2));
+    }
+
+    #[test]
+    fn seeds_deserialize_into_the_arena() {
+        let mut arena = [0u32; 8];
+        {
+            let splitter = SyncSplitter::new(&mut arena);
+            let load =
+                |json| ArenaSeed::new(&splitter).deserialize(&mut Deserializer::from_str(json));
+            let load_used =
+                |json| UsedArenaSeed::new(&splitter).deserialize(&mut Deserializer::from_str(json));
+            splitter.pop().unwrap();
+            assert_eq!(load("[1, 2, 3]").unwrap(), (1, 3));
+            assert_eq!(load("[]").unwrap(), (4, 0));
+            let json = r#"{"root": 1, "len": 2, "elements": [4, 5]}"#;
+            assert_eq!(load_used(json).unwrap(), (4, 2, Some(5)));
+
+            assert!(load_used(r#"{"len": 1, "root": null, "elements": []}"#).is_err());
+            assert!(load_used(r#"{"elements": [6], "len": 1, "root": null}"#).is_err());
+            assert!(load_used(r#"{"len": 9, "root": null, "elements": []}"#).is_err());
+            assert!(load("[6, 7, 8]").is_err());
+        }
+        assert_eq!(arena[..6], [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn rejected_chunks_give_their_elements_back() {
+        let mut arena = [0u32; 4];
+        let splitter = SyncSplitter::new(&mut arena);
+        let load_used =
+            |json| UsedArenaSeed::new(&splitter).deserialize(&mut Deserializer::from_str(json));
+        let errors = [
+            r#"{"len": 1, "root": 5, "elements": [1]}"#,
+            r#"{"len": 1, "root": 18446744073709551615, "elements": [1]}"#,
+            r#"{"len": 1, "elements": [1], "root": 1}"#,
+            r#"{"len": 2, "root": null, "elements": [1]}"#,
+            r#"[2, 2, [1, 2]]"#,
+            r#"[2, 0, [1, 2, 3]]"#,
+        ];
+        for json in &errors {
+            assert!(load_used(json).is_err(), "{}", json);
+            assert_eq!(splitter.next.load(), 0, "{}", json);
+        }
+        assert_eq!(load_used("[2, 1, [1, 2]]").unwrap(), (0, 2, Some(1)));
+    }
+
+    #[test]
+    fn chunks_load_in_parallel() {
+        let chunks = (0..8u32)
+            .map(|chunk| {
+                let elements = (0..100).map(|value| chunk * 1000 + value).collect::<Vec<_>>();
+                serde_json::to_string(&UsedArena::new(&elements, 100, Some(99))).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut arena = vec![0u32; 800];
+        let loaded = {
+            let splitter = SyncSplitter::new(&mut arena);
+            let loaded = ::std::thread::scope(|scope| {
+                let threads = chunks
+                    .iter()
+                    .map(|chunk| {
+                        let splitter = &splitter;
+                        scope.spawn(move || {
+                            let mut deserializer = Deserializer::from_str(chunk);
+                            UsedArenaSeed::new(splitter).deserialize(&mut deserializer).unwrap()
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                threads
+                    .into_iter()
+                    .map(|thread| thread.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(splitter.done(), 800);
+            loaded
+        };
+        for (chunk, &(start, len, root)) in loaded.iter().enumerate() {
+            assert_eq!((len, root), (100, Some(start + 99)));
+            assert_eq!(arena[start], chunk as u32 * 1000);
+            assert_eq!(arena[start + 99], chunk as u32 * 1000 + 99);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Splitting several discontiguous slices as if they were one.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::slice;
+
+use cursor::Cursor;
+
+/// A `SegmentedSplitter` allows multiple threads to pop from a fixed set of slices (e.g. multiple
+/// pools or per-socket buffers) at the same time, as if they were a single one.
+///
+/// Segments are popped from in order, moving on to the next segment once a pop doesn't fit in the
+/// current one, so a pop never straddles two segments. Indices are global: a segment's elements
+/// are numbered after those of every segment before it, as if the segments were concatenated.
+///
+/// This allows huge logical arenas on systems where a single contiguous allocation of that size
+/// fails.
+///
+/// On NUMA machines, segments can be tagged with the node they were allocated on using
+/// `with_nodes`. Then `pop_near` and `pop_n_near` prefer segments on the caller's node, which keeps
+/// writes node-local for as long as that node's segments have space left.
+pub struct SegmentedSplitter<'a, T: 'a + Sync> {
+    segments: Vec<Segment>,
+    /// The global index of each segment's first element, followed by the total length.
+    starts: Vec<usize>,
+    /// The index of the segment being popped from.
+    current: Cursor,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+struct Segment {
+    data: *mut (),
+    len: usize,
+    node: usize,
+    next: Cursor,
+    /// The number of elements popped by `pop_near` or `pop_n_near` from another node.
+    remote: Cursor,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> SegmentedSplitter<'a, T> {
+    /// Creates a new `SegmentedSplitter` over `segments`, in order. Every segment is on node `0`.
+    pub fn new<I: IntoIterator<Item = &'a mut [T]>>(segments: I) -> Self {
+        SegmentedSplitter::with_nodes(segments.into_iter().map(|segment| (segment, 0)))
+    }
+
+    /// Creates a new `SegmentedSplitter` over `segments`, in order, each tagged with the NUMA node
+    /// it was allocated on (as numbered by the operating system, or by any other scheme which is
+    /// also used for `pop_near`).
+    pub fn with_nodes<I: IntoIterator<Item = (&'a mut [T], usize)>>(segments: I) -> Self {
+        let segments = segments
+            .into_iter()
+            .map(|(segment, node)| Segment {
+                data: segment.as_mut_ptr() as *mut (),
+                len: segment.len(),
+                node,
+                next: Cursor::new(0),
+                remote: Cursor::new(0),
+            })
+            .collect::<Vec<_>>();
+        let mut starts = Vec::with_capacity(segments.len() + 1);
+        starts.push(0);
+        for segment in &segments {
+            let start = starts[starts.len() - 1];
+            starts.push(start + segment.len);
+        }
+        SegmentedSplitter {
+            segments,
+            starts,
+            current: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the number of segments.
+    #[inline]
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns the total number of elements across all segments.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.starts[self.segments.len()]
+    }
+
+    /// Returns `true` if the segments have no elements at all.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the node of the segment at `index`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= self.num_segments()`.
+    #[inline]
+    pub fn segment_node(&self, index: usize) -> usize {
+        self.segments[index].node
+    }
+
+    /// Returns the segment holding the element with the given global index and the element's
+    /// index within that segment.
+    ///
+    /// Returns `None` if `index >= self.len()`.
+    pub fn segment_of(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.len() {
+            return None;
+        }
+        let segment = self.starts.partition_point(|&start| start <= index) - 1;
+        Some((segment, index - self.starts[segment]))
+    }
+
+    /// Pops one mutable reference and returns it.
+    ///
+    /// Also returns the element's global index.
+    ///
+    /// Returns `None` if every segment was exhausted.
+    #[inline]
+    pub fn pop(&self) -> Option<(&mut T, usize)> {
+        self.bump(1).map(|(data, index)| (unsafe { &mut *data }, index))
+    }
+
+    /// Pops a mutable slice of a given length, from a single segment, and returns it.
+    ///
+    /// Also returns the global index of the slice's first element.
+    ///
+    /// Returns `None` if neither the current segment nor any later one has enough elements left.
+    #[inline]
+    pub fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
+        self.bump(len)
+            .map(|(data, index)| (unsafe { slice::from_raw_parts_mut(data, len) }, index))
+    }
+
+    /// Like `pop`, but prefers segments on `node`, only falling back to segments on other nodes
+    /// once those are full.
+    ///
+    /// Usually `node` is the one the calling thread runs on.
+    #[inline]
+    pub fn pop_near(&self, node: usize) -> Option<(&mut T, usize)> {
+        self.bump_near(node, 1).map(|(data, index)| (unsafe { &mut *data }, index))
+    }
+
+    /// Like `pop_n`, but prefers segments on `node`. See `pop_near`.
+    #[inline]
+    pub fn pop_n_near(&self, node: usize, len: usize) -> Option<(&mut [T], usize)> {
+        self.bump_near(node, len)
+            .map(|(data, index)| (unsafe { slice::from_raw_parts_mut(data, len) }, index))
+    }
+
+    /// Returns how much of each segment was popped so far.
+    pub fn segment_usage(&self) -> Vec<SegmentUsage> {
+        self.segments
+            .iter()
+            .map(|segment| SegmentUsage {
+                node: segment.node,
+                popped: segment.next.load(),
+                popped_remotely: segment.remote.load(),
+            })
+            .collect()
+    }
+
+    /// Consumes the splitter and returns how much of each segment was popped.
+    pub fn done(self) -> Vec<SegmentUsage> {
+        self.segment_usage()
+    }
+
+    /// Like `bump`, but tries every segment on `node` first and then every other one, without
+    /// moving `current`.
+    fn bump_near(&self, node: usize, len: usize) -> Option<(*mut T, usize)> {
+        let local = self.segments.iter().enumerate().filter(|(_, segment)| segment.node == node);
+        let remote = self.segments.iter().enumerate().filter(|(_, segment)| segment.node != node);
+        for (index, segment) in local.chain(remote) {
+            if let Some(offset) = segment.next.bump(segment.len, len) {
+                if segment.node != node {
+                    segment.remote.update(|remote| Some(((), remote + len)));
+                }
+                let data = unsafe { (segment.data as *mut T).add(offset) };
+                return Some((data, self.starts[index] + offset));
+            }
+        }
+        None
+    }
+
+    /// Reserves `len` elements in the current segment (or a later one), returning a pointer to the
+    /// first one and its global index.
+    fn bump(&self, len: usize) -> Option<(*mut T, usize)> {
+        let mut current = self.current.load();
+        loop {
+            let segment = self.segments.get(current)?;
+            if let Some(index) = segment.next.bump(segment.len, len) {
+                let data = unsafe { (segment.data as *mut T).add(index) };
+                return Some((data, self.starts[current] + index));
+            }
+            // A pop which doesn't fit any segment shouldn't use them all up.
+            if self.segments[current..].iter().all(|segment| segment.len < len) {
+                return None;
+            }
+            // Move on to the next segment, unless another thread already did.
+            self.current.update(|latest| {
+                if latest == current {
+                    Some(((), current + 1))
+                } else {
+                    None
+                }
+            });
+            current = self.current.load();
+        }
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for SegmentedSplitter<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for SegmentedSplitter<'a, T> {}
+
+/// How much of a segment of a `SegmentedSplitter` was popped. See
+/// `SegmentedSplitter::segment_usage`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SegmentUsage {
+    node: usize,
+    popped: usize,
+    popped_remotely: usize,
+}
+
+impl SegmentUsage {
+    /// Returns the node the segment is on.
+    #[inline]
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    /// Returns the number of elements popped from the segment.
+    #[inline]
+    pub fn popped(&self) -> usize {
+        self.popped
+    }
+
+    /// Returns the number of elements popped from the segment by `pop_near` or `pop_n_near` on
+    /// behalf of another node.
+    #[inline]
+    pub fn popped_remotely(&self) -> usize {
+        self.popped_remotely
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SegmentUsage, SegmentedSplitter};
+
+    #[test]
+    fn pops_move_on_to_later_segments() {
+        let (mut first, mut second, mut third) = ([0u32; 4], [0u32; 0], [0u32; 8]);
+        {
+            let splitter = SegmentedSplitter::new(vec![&mut first[..], &mut second, &mut third]);
+            assert_eq!((splitter.num_segments(), splitter.len()), (3, 12));
+            assert_eq!(splitter.segment_of(4), Some((2, 0)));
+            assert_eq!(splitter.segment_of(3), Some((0, 3)));
+            assert_eq!(splitter.segment_of(12), None);
+
+            let (head, index) = splitter.pop_n(3).unwrap();
+            head.copy_from_slice(&[1, 2, 3]);
+            assert_eq!(index, 0);
+            assert!(splitter.pop_n(9).is_none());
+            let (tail, index) = splitter.pop_n(2).unwrap();
+            tail.copy_from_slice(&[4, 5]);
+            assert_eq!(index, 4);
+            assert_eq!(splitter.pop().map(|(_, index)| index), Some(6));
+            let popped = splitter.done().iter().map(|usage| usage.popped()).collect::<Vec<_>>();
+            assert_eq!(popped, [3, 0, 3]);
+        }
+        assert_eq!(first, [1, 2, 3, 0]);
+        assert_eq!(third[..2], [4, 5]);
+    }
+
+    #[test]
+    fn pops_from_many_threads_are_disjoint() {
+        let mut segments = (0..5).map(|len| vec![0usize; 100 * (len + 1)]).collect::<Vec<_>>();
+        let total = {
+            let segments = segments.iter_mut().map(|segment| &mut segment[..]);
+            let splitter = SegmentedSplitter::new(segments);
+            ::std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    let splitter = &splitter;
+                    scope.spawn(move || {
+                        while let Some((elements, index)) = splitter.pop_n(3) {
+                            for (offset, element) in elements.iter_mut().enumerate() {
+                                assert_eq!(*element, 0);
+                                *element = index + offset + 1;
+                            }
+                        }
+                    });
+                }
+            });
+            splitter.done().iter().map(|usage| usage.popped()).sum::<usize>()
+        };
+        let values = segments.iter().flatten().filter(|&&value| value != 0).count();
+        assert_eq!(values, total);
+        let mut start = 0;
+        for segment in &segments {
+            for (offset, &value) in segment.iter().enumerate() {
+                assert!(value == 0 || value == start + offset + 1);
+            }
+            start += segment.len();
+        }
+    }
+
+    #[test]
+    fn pops_near_a_node_prefer_its_segments() {
+        let (mut first, mut second, mut third) = ([0u8; 4], [0u8; 4], [0u8; 4]);
+        let splitter = SegmentedSplitter::with_nodes(vec![
+            (&mut first[..], 0),
+            (&mut second[..], 1),
+            (&mut third[..], 1),
+        ]);
+        assert_eq!(splitter.segment_node(2), 1);
+        assert_eq!(splitter.pop_n_near(1, 3).map(|(_, index)| index), Some(4));
+        assert_eq!(splitter.pop_n_near(1, 2).map(|(_, index)| index), Some(8));
+        assert_eq!(splitter.pop_near(0).map(|(_, index)| index), Some(0));
+        assert_eq!(splitter.pop_n_near(1, 3).map(|(_, index)| index), Some(1));
+        assert!(splitter.pop_n_near(0, 3).is_none());
+        let usage = |node, popped, popped_remotely| SegmentUsage {
+            node,
+            popped,
+            popped_remotely,
+        };
+        assert_eq!(
+            splitter.done(),
+            [usage(0, 4, 3), usage(1, 3, 0), usage(1, 2, 0)]
+        );
+    }
+}
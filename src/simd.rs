@@ -0,0 +1,65 @@
+//! Aligned SIMD vector pops, available with the `simd` feature on nightly compilers.
+
+use core::mem;
+use core::simd::{Simd, SimdElement};
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync + SimdElement> SyncSplitter<'a, T> {
+    /// Pops `LANES` consecutive elements as a mutable SIMD vector and returns it.
+    ///
+    /// Also returns the offset of the vector's first element into the original slice.
+    ///
+    /// The vector is always properly aligned: any elements needed to pad the current position up to
+    /// the vector's alignment are skipped (left untouched, but counted by `done()`).
+    ///
+    /// Returns `None` if the underlying slice doesn't have enough elements left.
+    #[inline]
+    pub fn pop_simd<const LANES: usize>(&self) -> Option<(&mut Simd<T, LANES>, usize)> {
+        self.bump_aligned(LANES, mem::align_of::<Simd<T, LANES>>())
+            .map(|index| {
+                (
+                    unsafe { &mut *(self.data.add(index) as *mut Simd<T, LANES>) },
+                    self.offset + index,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use std::simd::Simd;
+    use SyncSplitter;
+
+    #[test]
+    fn pop_simd_is_aligned() {
+        let mut buffer = [0f32; 64];
+        let splitter = SyncSplitter::new(&mut buffer[1..]);
+
+        let (first, first_index) = splitter.pop().unwrap();
+        *first = 1.0;
+        assert_eq!(first_index, 0);
+
+        let mut last_end = 1;
+        while let Some((vector, index)) = splitter.pop_simd::<4>() {
+            assert_eq!(vector as *mut _ as usize % mem::align_of::<Simd<f32, 4>>(), 0);
+            assert!(index >= last_end);
+            *vector = Simd::splat(2.0);
+            last_end = index + 4;
+        }
+        let popped = splitter.done();
+        assert!(popped <= 63 && popped > 59);
+        assert_eq!(buffer[1], 1.0);
+        assert_eq!(buffer[last_end], 2.0);
+    }
+
+    #[test]
+    fn pop_simd_returns_none_when_exhausted() {
+        let mut buffer = [0u8; 3];
+        let splitter = SyncSplitter::new(&mut buffer);
+        assert!(splitter.pop_simd::<64>().is_none());
+        assert_eq!(splitter.done(), 0);
+    }
+}
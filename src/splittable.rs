@@ -0,0 +1,172 @@
+//! The pops shared by `SyncSplitter` and `MutexSplitter`, for code which works with either.
+
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use core::slice;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+use SyncSplitter;
+
+/// A splitter, from which multiple threads can pop elements of a slice at the same time.
+///
+/// Write code against this trait to run it with either `SyncSplitter` or the (slow, but trivially
+/// correct) `MutexSplitter`, e.g. to compare the two in tests.
+#[allow(clippy::mut_from_ref)]
+pub trait Splittable<T> {
+    /// Pops one element. See `SyncSplitter::pop`.
+    fn pop(&self) -> Option<(&mut T, usize)>;
+
+    /// Pops two consecutive elements. See `SyncSplitter::pop_two`.
+    fn pop_two(&self) -> Option<((&mut T, &mut T), usize)>;
+
+    /// Pops `len` consecutive elements. See `SyncSplitter::pop_n`.
+    fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)>;
+
+    /// Consumes the splitter and returns the total number of popped elements. See
+    /// `SyncSplitter::done`.
+    fn done(self) -> usize
+    where
+        Self: Sized;
+}
+
+impl<'a, T: 'a + Sync> Splittable<T> for SyncSplitter<'a, T> {
+    #[inline]
+    fn pop(&self) -> Option<(&mut T, usize)> {
+        SyncSplitter::pop(self)
+    }
+
+    #[inline]
+    fn pop_two(&self) -> Option<((&mut T, &mut T), usize)> {
+        SyncSplitter::pop_two(self)
+    }
+
+    #[inline]
+    fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
+        SyncSplitter::pop_n(self, len)
+    }
+
+    #[inline]
+    fn done(self) -> usize {
+        SyncSplitter::done(self)
+    }
+}
+
+/// A splitter which guards its cursor with a `Mutex`.
+///
+/// Only available with the `std` feature.
+///
+/// It's much slower than a `SyncSplitter` under contention, but obviously correct, which makes it
+/// a reference to test `SyncSplitter` (or code built on `Splittable`) against, and a fallback for
+/// platforms where the atomics behind `SyncSplitter` misbehave.
+#[cfg(feature = "std")]
+pub struct MutexSplitter<'a, T: 'a + Sync> {
+    data: *mut T,
+    len: usize,
+    next: Mutex<usize>,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+#[cfg(feature = "std")]
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> MutexSplitter<'a, T> {
+    /// Creates a new `MutexSplitter` from a slice.
+    pub fn new(slice: &'a mut [T]) -> Self {
+        MutexSplitter {
+            data: slice.as_mut_ptr(),
+            len: slice.len(),
+            next: Mutex::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Pops `len` consecutive elements and returns them, along with their offset into the
+    /// original slice.
+    ///
+    /// Returns `None` if not enough elements were left in the underlying slice.
+    pub fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
+        let mut next = self.next.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if len > self.len - *next {
+            return None;
+        }
+        let index = *next;
+        *next += len;
+        Some((unsafe { slice::from_raw_parts_mut(self.data.add(index), len) }, index))
+    }
+
+    /// Consumes the splitter and returns the total number of popped elements.
+    pub fn done(self) -> usize {
+        self.next.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T: 'a + Sync> Splittable<T> for MutexSplitter<'a, T> {
+    #[inline]
+    fn pop(&self) -> Option<(&mut T, usize)> {
+        MutexSplitter::pop_n(self, 1).map(|(elements, index)| (&mut elements[0], index))
+    }
+
+    #[inline]
+    fn pop_two(&self) -> Option<((&mut T, &mut T), usize)> {
+        MutexSplitter::pop_n(self, 2).map(|(elements, index)| {
+            let (first, second) = elements.split_at_mut(1);
+            ((&mut first[0], &mut second[0]), index)
+        })
+    }
+
+    #[inline]
+    fn pop_n(&self, len: usize) -> Option<(&mut [T], usize)> {
+        MutexSplitter::pop_n(self, len)
+    }
+
+    #[inline]
+    fn done(self) -> usize {
+        MutexSplitter::done(self)
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl<'a, T: Sync> Sync for MutexSplitter<'a, T> {}
+#[cfg(feature = "std")]
+unsafe impl<'a, T: Send + Sync> Send for MutexSplitter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::Splittable;
+    #[cfg(feature = "std")]
+    use super::MutexSplitter;
+    use SyncSplitter;
+
+    /// Builds a complete binary tree of `(height, first child)` nodes.
+    fn build<S: Splittable<(u32, usize)>>(splitter: &S, node: &mut (u32, usize), height: u32) {
+        if height == 0 {
+            return;
+        }
+        let ((left, right), first_child) = splitter.pop_two().unwrap();
+        *node = (height, first_child);
+        build(splitter, left, height - 1);
+        build(splitter, right, height - 1);
+    }
+
+    fn build_tree<S: Splittable<(u32, usize)>>(splitter: S) -> usize {
+        let (root, _) = splitter.pop().unwrap();
+        build(&splitter, root, 4);
+        assert!(splitter.pop_n(2).is_none());
+        assert_eq!(splitter.pop_n(1).map(|(_, index)| index), Some(31));
+        splitter.done()
+    }
+
+    #[test]
+    fn implementations_agree() {
+        let mut expected = [(0u32, 0usize); 32];
+        assert_eq!(build_tree(SyncSplitter::new(&mut expected)), 32);
+        #[cfg(feature = "std")]
+        {
+            let mut actual = [(0u32, 0usize); 32];
+            assert_eq!(build_tree(MutexSplitter::new(&mut actual)), 32);
+            assert_eq!(actual, expected);
+        }
+    }
+}
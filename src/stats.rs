@@ -0,0 +1,329 @@
+//! Statistics about the pops requested from a splitter, available with the `stats` feature.
+
+use alloc::vec::Vec;
+use core::array;
+use core::ops::RangeInclusive;
+use core::slice;
+use core::str;
+
+use cursor::Cursor;
+use SyncSplitter;
+
+/// The number of buckets in a `PopSizeHistogram`.
+pub const POP_SIZE_BUCKETS: usize = 16;
+
+/// The number of distinct labels tracked per splitter. Pops with any further labels are reported
+/// under `OTHER_LABEL`.
+pub const MAX_LABELS: usize = 16;
+
+/// The label which `SyncSplitter::label_usage` reports pops under once `MAX_LABELS` labels are in
+/// use.
+pub const OTHER_LABEL: &str = "(other)";
+
+/// Counters updated on every pop.
+pub struct Stats {
+    pop_sizes: [Cursor; POP_SIZE_BUCKETS],
+    /// The last slot counts every label which didn't get a slot of its own.
+    labels: [LabelSlot; MAX_LABELS + 1],
+}
+
+/// The counters of a single label. A slot is claimed by setting `name_len` (to one more than the
+/// label's length, so zero means unclaimed) and then `name` (to the label's address).
+struct LabelSlot {
+    name_len: Cursor,
+    name: Cursor,
+    pops: Cursor,
+    failed_pops: Cursor,
+    elements: Cursor,
+}
+
+impl LabelSlot {
+    fn new() -> Self {
+        LabelSlot {
+            name_len: Cursor::new(0),
+            name: Cursor::new(0),
+            pops: Cursor::new(0),
+            failed_pops: Cursor::new(0),
+            elements: Cursor::new(0),
+        }
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        let (name_len, name) = (self.name_len.load(), self.name.load());
+        if name_len == 0 || name == 0 {
+            return None;
+        }
+        // Both were copied from a `&'static str`.
+        Some(unsafe {
+            str::from_utf8_unchecked(slice::from_raw_parts(name as *const u8, name_len - 1))
+        })
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            pop_sizes: array::from_fn(|_| Cursor::new(0)),
+            labels: array::from_fn(|_| LabelSlot::new()),
+        }
+    }
+
+    /// Records a pop of `len` elements, whether it succeeded or not.
+    #[inline]
+    pub fn record(&self, len: usize) {
+        let bucket = bucket(len).min(POP_SIZE_BUCKETS - 1);
+        self.pop_sizes[bucket].update(|count| Some(((), count.wrapping_add(1))));
+    }
+
+    /// Records a pop of `len` elements attributed to `label`.
+    pub fn record_label(&self, label: &'static str, len: usize, succeeded: bool) {
+        let slot = self.label_slot(label);
+        slot.pops.update(|pops| Some(((), pops.wrapping_add(1))));
+        if succeeded {
+            slot.elements.update(|elements| Some(((), elements.wrapping_add(len))));
+        } else {
+            slot.failed_pops.update(|failed| Some(((), failed.wrapping_add(1))));
+        }
+    }
+
+    fn label_slot(&self, label: &'static str) -> &LabelSlot {
+        let (slots, other) = self.labels.split_at(MAX_LABELS);
+        for slot in slots {
+            let claimed = slot.name_len.update(|name_len| {
+                if name_len == 0 {
+                    Some(((), label.len() + 1))
+                } else {
+                    None
+                }
+            });
+            if claimed.is_some() {
+                slot.name.update(|_| Some(((), label.as_ptr() as usize)));
+                return slot;
+            }
+            // Claiming never waits for another thread: if a slot was claimed but not named yet,
+            // the same label may end up in two slots, which `label_usage` merges.
+            if slot.label() == Some(label) {
+                return slot;
+            }
+        }
+        &other[0]
+    }
+}
+
+/// Bucket `0` holds sizes `0` and `1`, and bucket `k > 0` holds sizes in `2^(k-1) + 1..=2^k`.
+#[inline]
+fn bucket(len: usize) -> usize {
+    if len <= 1 {
+        0
+    } else {
+        (usize::BITS - (len - 1).leading_zeros()) as usize
+    }
+}
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Returns a snapshot of how many pops of each size were requested from this splitter so far,
+    /// including pops which didn't fit.
+    ///
+    /// Many small pops are a hint that batching them with `pop_n` (or `pop_splitter`) might reduce
+    /// contention. Counts are kept across `reset`, but not shared with splitters returned by
+    /// `pop_splitter`.
+    pub fn pop_size_histogram(&self) -> PopSizeHistogram {
+        let mut counts = [0; POP_SIZE_BUCKETS];
+        for (count, cursor) in counts.iter_mut().zip(&self.stats.pop_sizes) {
+            *count = cursor.load();
+        }
+        PopSizeHistogram { counts }
+    }
+
+    /// Returns the pops attributed to each label with `pop_labeled` or `pop_n_labeled` so far,
+    /// in the order the labels were first used.
+    ///
+    /// Only `MAX_LABELS` distinct labels are tracked: pops with any further labels are counted
+    /// under `OTHER_LABEL`. As with `pop_size_histogram`, counts are kept across `reset`.
+    pub fn label_usage(&self) -> Vec<LabelUsage> {
+        let mut usage: Vec<LabelUsage> = Vec::new();
+        for slot in &self.stats.labels {
+            let label = match slot.label() {
+                Some(label) => label,
+                None if slot.pops.load() > 0 => OTHER_LABEL,
+                None => continue,
+            };
+            let index = match usage.iter().position(|usage| usage.label == label) {
+                Some(index) => index,
+                None => {
+                    usage.push(LabelUsage {
+                        label,
+                        pops: 0,
+                        failed_pops: 0,
+                        elements: 0,
+                    });
+                    usage.len() - 1
+                }
+            };
+            usage[index].pops += slot.pops.load();
+            usage[index].failed_pops += slot.failed_pops.load();
+            usage[index].elements += slot.elements.load();
+        }
+        usage
+    }
+}
+
+/// The pops attributed to one label. See `SyncSplitter::label_usage`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LabelUsage {
+    label: &'static str,
+    pops: usize,
+    failed_pops: usize,
+    elements: usize,
+}
+
+impl LabelUsage {
+    /// Returns the label.
+    #[inline]
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    /// Returns the number of pops with this label, including ones which didn't fit.
+    #[inline]
+    pub fn pops(&self) -> usize {
+        self.pops
+    }
+
+    /// Returns the number of pops with this label which didn't fit.
+    #[inline]
+    pub fn failed_pops(&self) -> usize {
+        self.failed_pops
+    }
+
+    /// Returns the total number of elements popped with this label.
+    #[inline]
+    pub fn elements(&self) -> usize {
+        self.elements
+    }
+}
+
+/// The number of pops requested from a splitter, by size. See `SyncSplitter::pop_size_histogram`.
+///
+/// Sizes are grouped in power-of-two buckets: `0..=1`, `2..=2`, `3..=4`, `5..=8` and so on, with
+/// the last bucket also counting every larger size.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PopSizeHistogram {
+    counts: [usize; POP_SIZE_BUCKETS],
+}
+
+impl PopSizeHistogram {
+    /// Returns the number of pops in each bucket.
+    #[inline]
+    pub fn counts(&self) -> &[usize; POP_SIZE_BUCKETS] {
+        &self.counts
+    }
+
+    /// Returns the range of sizes counted by the bucket at `index`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `index >= POP_SIZE_BUCKETS`.
+    pub fn bucket_sizes(index: usize) -> RangeInclusive<usize> {
+        assert!(index < POP_SIZE_BUCKETS, "invalid bucket {}", index);
+        match index {
+            0 => 0..=1,
+            _ if index == POP_SIZE_BUCKETS - 1 => (1 << (index - 1)) + 1..=usize::MAX,
+            _ => (1 << (index - 1)) + 1..=1 << index,
+        }
+    }
+
+    /// Returns the total number of pops.
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Iterates over the non-empty buckets, as the range of sizes each one counts and its count.
+    pub fn iter(&self) -> impl Iterator<Item = (RangeInclusive<usize>, usize)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(index, &count)| (PopSizeHistogram::bucket_sizes(index), count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, PopSizeHistogram, MAX_LABELS, OTHER_LABEL, POP_SIZE_BUCKETS};
+    use SyncSplitter;
+
+    #[test]
+    fn buckets_match_their_sizes() {
+        for index in 0..POP_SIZE_BUCKETS {
+            let sizes = PopSizeHistogram::bucket_sizes(index);
+            assert_eq!(bucket(*sizes.start()).min(POP_SIZE_BUCKETS - 1), index);
+            assert_eq!(bucket(*sizes.end()).min(POP_SIZE_BUCKETS - 1), index);
+        }
+        assert_eq!(PopSizeHistogram::bucket_sizes(3), 5..=8);
+    }
+
+    #[test]
+    fn histogram_counts_requested_sizes() {
+        let mut buffer = [0u8; 64];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.pop();
+        splitter.pop_two();
+        splitter.pop_n(3);
+        splitter.pop_n(4);
+        splitter.pop_n(100);
+        splitter.reset();
+        splitter.pop_n(0);
+
+        let histogram = splitter.pop_size_histogram();
+        assert_eq!(histogram.total(), 6);
+        assert_eq!(
+            histogram.iter().collect::<Vec<_>>(),
+            [(0..=1, 2), (2..=2, 1), (3..=4, 2), (65..=128, 1)]
+        );
+    }
+
+    #[test]
+    fn label_usage_counts_labeled_pops() {
+        let mut buffer = [0u8; 64];
+        let splitter = SyncSplitter::new(&mut buffer);
+        splitter.pop_n_labeled("leaves", 10);
+        splitter.pop_labeled("internal");
+        splitter.pop_n_labeled("leaves", 50);
+        splitter.pop_n_labeled("internal", 10);
+        splitter.pop();
+
+        let usage = splitter
+            .label_usage()
+            .iter()
+            .map(|usage| (usage.label(), usage.pops(), usage.failed_pops(), usage.elements()))
+            .collect::<Vec<_>>();
+        assert_eq!(usage, [("leaves", 2, 0, 60), ("internal", 2, 1, 1)]);
+    }
+
+    #[test]
+    fn label_usage_merges_labels_from_many_threads() {
+        const LABELS: [&str; MAX_LABELS + 2] = [
+            "0", "1", "2", "3", "4", "5", "6", "7", "8",
+            "9", "a", "b", "c", "d", "e", "f", "g", "h",
+        ];
+        let mut buffer = vec![0u8; 1 << 16];
+        let splitter = SyncSplitter::new(&mut buffer);
+        ::std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let splitter = &splitter;
+                scope.spawn(move || {
+                    for label in LABELS.iter() {
+                        splitter.pop_n_labeled(label, 2);
+                    }
+                });
+            }
+        });
+        let usage = splitter.label_usage();
+        assert!(usage.len() <= MAX_LABELS + 1);
+        let elements = usage.iter().map(|usage| usage.elements()).sum::<usize>();
+        assert_eq!(elements, 4 * 2 * LABELS.len());
+        assert!(usage.iter().any(|usage| usage.label() == OTHER_LABEL));
+    }
+}
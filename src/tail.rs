@@ -0,0 +1,67 @@
+//! Handing the unpopped tail of a splitter to foreign code.
+
+use SyncSplitter;
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Pops every element which is left, without borrowing them, and returns a raw pointer to the
+    /// first one.
+    ///
+    /// Also returns the number of elements popped (which may be zero) and their offset into the
+    /// original slice. Every later pop fails.
+    ///
+    /// This is meant for handing the rest of the slice to C or GPU code which fills it in under
+    /// its own synchronization, where a Rust borrow can't follow.
+    ///
+    /// Safety
+    /// ===
+    ///
+    /// Calling this is safe, but using the pointer is only sound while the original slice is still
+    /// borrowed: the elements may be read and written through it for as long as the splitter (or
+    /// anything else borrowing the original slice for `'a`) is alive, and it must not be used once
+    /// the original slice can be accessed again. Since no Rust reference covers the returned
+    /// elements, synchronizing their accesses, and any reads of them from Rust once the slice is
+    /// accessible again, is entirely up to the caller.
+    pub fn pop_tail_raw(&self) -> (*mut T, usize, usize) {
+        let index = self
+            .next
+            .update(|index| Some((index, self.len)))
+            .unwrap_or(self.len);
+        if index < self.len {
+            self.advanced(index, self.len);
+        }
+        (
+            unsafe { self.data.add(index) },
+            self.len - index,
+            self.offset + index,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use SyncSplitter;
+
+    #[test]
+    fn pop_tail_raw_claims_the_rest() {
+        let mut buffer = [0u32; 6];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            splitter.pop_two();
+            let (data, len, offset) = splitter.pop_tail_raw();
+            assert_eq!((len, offset), (4, 2));
+            assert!(splitter.pop().is_none());
+            assert_eq!(splitter.pop_tail_raw().1, 0);
+
+            ::std::thread::scope(|scope| {
+                let data = data as usize;
+                scope.spawn(move || {
+                    for index in 0..len {
+                        unsafe { *(data as *mut u32).add(index) = index as u32 + 1 };
+                    }
+                });
+            });
+            assert_eq!(splitter.done(), 6);
+        }
+        assert_eq!(buffer, [0, 0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Callbacks fired when a splitter's utilization crosses given thresholds.
+
+use alloc::boxed::Box;
+
+use no_unwind;
+use SyncSplitter;
+
+pub struct Threshold<'a> {
+    at: usize,
+    callback: Box<dyn Fn(usize) + Send + Sync + 'a>,
+}
+
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Registers a callback fired when the fraction of popped elements reaches `fraction` of the
+    /// slice's length.
+    ///
+    /// The callback is called with the number of popped elements, right after the pop which
+    /// crossed the threshold and on the thread which performed it. It fires once per crossing:
+    /// only again if the splitter is rewound below the threshold (see `reset`) and crosses it anew.
+    /// This gives producers a chance to switch to a coarser level of detail, or start flushing,
+    /// before the slice actually runs out. Pops never panic, so a panicking callback aborts.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `fraction` is not in `(0, 1]`.
+    pub fn on_threshold<F>(&mut self, fraction: f64, callback: F)
+    where
+        F: Fn(usize) + Send + Sync + 'a,
+    {
+        assert!(fraction > 0.0 && fraction <= 1.0, "invalid threshold {}", fraction);
+        // Rounds up, without `f64::ceil` which isn't available without `std`.
+        let exact = self.len as f64 * fraction;
+        let at = exact as usize;
+        let at = if (at as f64) < exact { at + 1 } else { at };
+        let at = at.clamp(1, self.len.max(1));
+        self.on_threshold_count(at, callback);
+    }
+
+    /// Like `on_threshold`, but with the threshold given as a number of popped elements.
+    ///
+    /// A threshold of zero never fires.
+    pub fn on_threshold_count<F>(&mut self, at: usize, callback: F)
+    where
+        F: Fn(usize) + Send + Sync + 'a,
+    {
+        self.thresholds.push(Threshold {
+            at,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Fires the callbacks of thresholds crossed by moving the cursor from `before` to `after`.
+    #[inline]
+    pub(crate) fn crossed(&self, before: usize, after: usize) {
+        if !self.thresholds.is_empty() {
+            no_unwind(|| fire_thresholds(&self.thresholds, before, after));
+        }
+    }
+}
+
+#[cold]
+fn fire_thresholds(thresholds: &[Threshold], before: usize, after: usize) {
+    for threshold in thresholds {
+        if before < threshold.at && after >= threshold.at {
+            (threshold.callback)(after);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use SyncSplitter;
+
+    #[test]
+    fn thresholds_fire_once_per_crossing() {
+        let fired = Mutex::new(Vec::new());
+        let mut buffer = [0u32; 10];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.on_threshold(0.75, |popped| fired.lock().unwrap().push((75, popped)));
+        splitter.on_threshold(0.5, |popped| fired.lock().unwrap().push((50, popped)));
+        splitter.on_threshold_count(10, |popped| fired.lock().unwrap().push((100, popped)));
+
+        splitter.pop_n(4);
+        assert!(fired.lock().unwrap().is_empty());
+        splitter.pop_n(4);
+        assert_eq!(*fired.lock().unwrap(), [(75, 8), (50, 8)]);
+        splitter.pop();
+        assert!(splitter.pop_two().is_none());
+        splitter.pop();
+        assert_eq!(fired.lock().unwrap().len(), 3);
+        assert_eq!(splitter.high_water_mark(), 10);
+
+        splitter.reset();
+        splitter.pop_n(5);
+        assert_eq!(*fired.lock().unwrap(), [(75, 8), (50, 8), (100, 10), (50, 5)]);
+    }
+
+    #[test]
+    fn thresholds_fire_once_across_threads() {
+        let fired = AtomicUsize::new(0);
+        let mut buffer = vec![0u32; 1000];
+        let mut splitter = SyncSplitter::new(&mut buffer);
+        splitter.on_threshold(0.9, |_| {
+            fired.fetch_add(1, Ordering::SeqCst);
+        });
+        ::std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| while splitter.pop().is_some() {});
+            }
+        });
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+    }
+}
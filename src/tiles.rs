@@ -0,0 +1,321 @@
+//! Splitting two dimensional buffers (like images) into tiles, optionally in Morton order.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::slice;
+
+use cursor::Cursor;
+
+/// The order in which a `TileSplitter` hands out its tiles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum TileOrder {
+    /// Row by row, with `x` varying fastest. This is the default.
+    #[default]
+    RowMajor,
+    /// Along a Morton (Z-order) curve, so tiles popped around the same time are close to each
+    /// other in both dimensions, not just along a row.
+    ///
+    /// This keeps concurrently processed tiles near each other, which helps workloads which read
+    /// their neighbors (like building image pyramids or baking textures).
+    Morton,
+}
+
+/// A `TileSplitter` allows multiple threads to claim non-overlapping, axis-aligned tiles of a two
+/// dimensional buffer at the same time.
+///
+/// The buffer holds `dims[0] * dims[1]` elements, with `x` varying fastest. Tiles are the cells of
+/// a grid of fixed-size tiles, handed out in the splitter's `TileOrder`, and tiles at the far
+/// edges of the buffer are clipped to it.
+pub struct TileSplitter<'a, T: 'a + Sync> {
+    data: *mut T,
+    dims: [usize; 2],
+    tile: [usize; 2],
+    grid: [usize; 2],
+    /// The grid cell of every tile, in Morton order, or `None` for `TileOrder::RowMajor`.
+    morton: Option<Vec<[usize; 2]>>,
+    /// The number of tiles popped so far.
+    next: Cursor,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> TileSplitter<'a, T> {
+    /// Creates a new `TileSplitter` over an image of the given dimensions, stored in `buffer`,
+    /// which hands out tiles of size `tile` in row-major order.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `buffer` is shorter than `dims[0] * dims[1]`, or if either dimension of `tile` is zero.
+    pub fn new(buffer: &'a mut [T], dims: [usize; 2], tile: [usize; 2]) -> Self {
+        TileSplitter::with_order(buffer, dims, tile, TileOrder::RowMajor)
+    }
+
+    /// Like `new`, but hands out tiles in the given order.
+    ///
+    /// `TileOrder::Morton` sorts the grid's cells along the curve up front, which allocates a
+    /// vector with an entry per tile.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `buffer` is shorter than `dims[0] * dims[1]`, or if either dimension of `tile` is zero.
+    pub fn with_order(
+        buffer: &'a mut [T],
+        dims: [usize; 2],
+        tile: [usize; 2],
+        order: TileOrder,
+    ) -> Self {
+        assert!(
+            dims[0].checked_mul(dims[1]).is_some_and(|len| len <= buffer.len()),
+            "image of {:?} elements doesn't fit in {}",
+            dims,
+            buffer.len()
+        );
+        assert!(tile[0] > 0 && tile[1] > 0, "empty tile size {:?}", tile);
+        let grid = [0, 1].map(|axis| dims[axis].div_ceil(tile[axis]));
+        let morton = match order {
+            TileOrder::RowMajor => None,
+            TileOrder::Morton => {
+                let mut cells = (0..grid[1])
+                    .flat_map(|y| (0..grid[0]).map(move |x| [x, y]))
+                    .collect::<Vec<_>>();
+                cells.sort_unstable_by_key(|&cell| morton_index(cell));
+                Some(cells)
+            }
+        };
+        TileSplitter {
+            data: buffer.as_mut_ptr(),
+            dims,
+            tile,
+            grid,
+            morton,
+            next: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the dimensions of the image, in elements.
+    #[inline]
+    pub fn dims(&self) -> [usize; 2] {
+        self.dims
+    }
+
+    /// Returns the size of a tile which isn't clipped by the image's edges.
+    #[inline]
+    pub fn tile_size(&self) -> [usize; 2] {
+        self.tile
+    }
+
+    /// Returns the number of tiles along each dimension.
+    #[inline]
+    pub fn grid(&self) -> [usize; 2] {
+        self.grid
+    }
+
+    /// Returns the order in which tiles are handed out.
+    #[inline]
+    pub fn order(&self) -> TileOrder {
+        match self.morton {
+            Some(_) => TileOrder::Morton,
+            None => TileOrder::RowMajor,
+        }
+    }
+
+    /// Pops the next tile and returns a mutable view over it.
+    ///
+    /// Also returns the tile's coordinates in the grid of tiles; its first element is at those
+    /// coordinates times the tile size. See `Tile::morton_index` for its position along the
+    /// Morton curve.
+    ///
+    /// Returns `None` if every tile was popped already.
+    pub fn pop_tile(&self) -> Option<(Tile<'_, T>, [usize; 2])> {
+        let index = self.next.bump(self.grid[0] * self.grid[1], 1)?;
+        let cell = match self.morton {
+            Some(ref cells) => cells[index],
+            None => [index % self.grid[0], index / self.grid[0]],
+        };
+        let origin = [0, 1].map(|axis| cell[axis] * self.tile[axis]);
+        Some((
+            Tile {
+                // Grid cells don't overlap, and every cell is popped once.
+                data: unsafe { self.data.add(origin[0] + self.dims[0] * origin[1]) },
+                cell,
+                origin,
+                size: [0, 1].map(|axis| self.tile[axis].min(self.dims[axis] - origin[axis])),
+                pitch: self.dims[0],
+                dummy: PhantomData,
+            },
+            cell,
+        ))
+    }
+
+    /// Consumes the splitter and returns the total number of popped tiles.
+    #[inline]
+    pub fn done(self) -> usize {
+        self.next.load()
+    }
+}
+
+/// Interleaves the bits of a grid cell's coordinates, `x` in the even bits and `y` in the odd
+/// ones, into its position along the Morton curve.
+///
+/// Only the low `usize::BITS / 2` bits of each coordinate fit in the result.
+pub fn morton_index(cell: [usize; 2]) -> usize {
+    (0..usize::BITS / 2).fold(0, |index, bit| {
+        index | (cell[0] >> bit & 1) << (2 * bit) | (cell[1] >> bit & 1) << (2 * bit + 1)
+    })
+}
+
+unsafe impl<'a, T: Sync> Sync for TileSplitter<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for TileSplitter<'a, T> {}
+
+/// A mutable view over a tile, popped from a `TileSplitter`.
+///
+/// The tile is made of `size()[1]` rows, each of them a contiguous run of `size()[0]` elements.
+pub struct Tile<'a, T: 'a> {
+    data: *mut T,
+    cell: [usize; 2],
+    origin: [usize; 2],
+    size: [usize; 2],
+    pitch: usize,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: 'a> Tile<'a, T> {
+    /// Returns the coordinates of the tile's first element in the image.
+    #[inline]
+    pub fn origin(&self) -> [usize; 2] {
+        self.origin
+    }
+
+    /// Returns the dimensions of the tile, which are smaller than the splitter's tile size at the
+    /// far edges of the image.
+    #[inline]
+    pub fn size(&self) -> [usize; 2] {
+        self.size
+    }
+
+    /// Returns the tile's position along the Morton curve over the grid of tiles. See
+    /// `morton_index`.
+    #[inline]
+    pub fn morton_index(&self) -> usize {
+        morton_index(self.cell)
+    }
+
+    /// Returns the row at `y`, relative to the tile's origin.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `y >= self.size()[1]`.
+    #[inline]
+    pub fn row(&self, y: usize) -> &[T] {
+        assert!(y < self.size[1], "row {} out of {}", y, self.size[1]);
+        unsafe { slice::from_raw_parts(self.data.add(y * self.pitch), self.size[0]) }
+    }
+
+    /// Returns the row at `y`, relative to the tile's origin, mutably.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `y >= self.size()[1]`.
+    #[inline]
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        assert!(y < self.size[1], "row {} out of {}", y, self.size[1]);
+        unsafe { slice::from_raw_parts_mut(self.data.add(y * self.pitch), self.size[0]) }
+    }
+
+    /// Returns an iterator over the rows of the tile, mutably.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut [T]> + '_ {
+        let (data, width, pitch) = (self.data, self.size[0], self.pitch);
+        (0..self.size[1]).map(move |y| unsafe {
+            // Rows don't overlap, since `width <= pitch`.
+            slice::from_raw_parts_mut(data.add(y * pitch), width)
+        })
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for Tile<'a, T> {}
+unsafe impl<'a, T: Send> Send for Tile<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{morton_index, TileOrder, TileSplitter};
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let dims = [7, 5];
+        for &order in &[TileOrder::RowMajor, TileOrder::Morton] {
+            let mut buffer = vec![0usize; 7 * 5 + 1];
+            {
+                let splitter = TileSplitter::with_order(&mut buffer, dims, [2, 2], order);
+                assert_eq!((splitter.grid(), splitter.order()), ([4, 3], order));
+                ::std::thread::scope(|scope| {
+                    for _ in 0..3 {
+                        let splitter = &splitter;
+                        scope.spawn(move || {
+                            while let Some((mut tile, cell)) = splitter.pop_tile() {
+                                assert_eq!(tile.origin(), cell.map(|coordinate| coordinate * 2));
+                                let id = cell[0] + 4 * cell[1] + 1;
+                                for row in tile.iter_mut() {
+                                    row.iter_mut().for_each(|element| *element += id);
+                                }
+                            }
+                        });
+                    }
+                });
+                assert_eq!(splitter.done(), 4 * 3);
+            }
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    assert_eq!(buffer[x + dims[0] * y], x / 2 + 4 * (y / 2) + 1);
+                }
+            }
+            assert_eq!(buffer[7 * 5], 0);
+        }
+    }
+
+    #[test]
+    fn morton_order_follows_the_curve() {
+        let mut buffer = [0u8; 4 * 3];
+        let splitter = TileSplitter::with_order(&mut buffer, [4, 3], [1, 1], TileOrder::Morton);
+        let mut cells = Vec::new();
+        let mut indices = Vec::new();
+        while let Some((tile, cell)) = splitter.pop_tile() {
+            cells.push(cell);
+            indices.push(tile.morton_index());
+        }
+        assert_eq!(
+            cells,
+            [
+                [0, 0], [1, 0], [0, 1], [1, 1],
+                [2, 0], [3, 0], [2, 1], [3, 1],
+                [0, 2], [1, 2], [2, 2], [3, 2],
+            ]
+        );
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 13]);
+        assert_eq!(morton_index([5, 3]), 0b011011);
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped() {
+        let mut buffer = [0u8; 9];
+        let splitter = TileSplitter::new(&mut buffer, [3, 3], [2, 3]);
+        let (first, _) = splitter.pop_tile().unwrap();
+        let (mut second, cell) = splitter.pop_tile().unwrap();
+        assert_eq!((first.size(), cell, second.size()), ([2, 3], [1, 0], [1, 3]));
+        second.row_mut(2)[0] = 7;
+        assert_eq!(second.row(2), [7]);
+        assert_eq!(second.origin(), [2, 0]);
+        assert!(splitter.pop_tile().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn small_buffers_panic() {
+        let mut buffer = [0u8; 3];
+        TileSplitter::new(&mut buffer, [2, 2], [1, 1]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Building trees in parallel without rayon, on a small work-stealing pool. Available with the
+//! `tree-builder` feature.
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+use std::iter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use SyncSplitter;
+
+/// Builds a tree into an arena using a fixed number of threads, which steal work from each other.
+///
+/// Every node is expanded exactly once, by calling a user-provided `expand(node, context)`
+/// function which creates the node's children (if any) with `Context::spawn_children`. The
+/// children are popped from a splitter over the arena and queued to be expanded in turn, possibly
+/// by another thread. The build finishes once every node was expanded.
+///
+/// Example
+/// ===
+/// ```rust
+/// use sync_splitter::ParallelTreeBuilder;
+///
+/// // Each node is `(height, first_child_index)`.
+/// let mut arena = vec![(0u32, 0usize); 127];
+/// let num_nodes = ParallelTreeBuilder::new(4).build(&mut arena, (6, 0), |node, context| {
+///     if node.0 > 0 {
+///         let height = node.0 - 1;
+///         node.1 = context
+///             .spawn_children(vec![(height, 0), (height, 0)])
+///             .expect("arena too small");
+///     }
+/// });
+/// assert_eq!(num_nodes, 127);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct ParallelTreeBuilder {
+    num_threads: usize,
+}
+
+impl ParallelTreeBuilder {
+    /// Creates a builder which uses `num_threads` threads. A `num_threads` of zero is treated as
+    /// one.
+    pub fn new(num_threads: usize) -> Self {
+        ParallelTreeBuilder {
+            num_threads: num_threads.max(1),
+        }
+    }
+
+    /// Returns the number of threads used by `build`.
+    #[inline]
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// Stores `root` at the start of `arena` and expands it, and all its descendants, with
+    /// `expand`.
+    ///
+    /// Returns the total number of nodes in the tree, which occupy the start of `arena`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `arena` is empty, if `arena.len() > isize::MAX`, or if `expand` panics (after all the
+    /// threads stopped).
+    pub fn build<T, E>(&self, arena: &mut [T], root: T, expand: E) -> usize
+    where
+        T: Send + Sync,
+        E: Fn(&mut T, &mut Context<T>) + Sync,
+    {
+        let splitter = SyncSplitter::new(arena);
+        let (root_slot, root_index) = splitter.pop().expect("arena is empty");
+        *root_slot = root;
+
+        let injector = Injector::new();
+        injector.push(root_index);
+        let pending = AtomicUsize::new(1);
+        let workers = (0..self.num_threads)
+            .map(|_| Worker::new_lifo())
+            .collect::<Vec<_>>();
+        let stealers = workers.iter().map(Worker::stealer).collect::<Vec<_>>();
+
+        thread::scope(|scope| {
+            for local in workers {
+                let shared = Shared {
+                    splitter: &splitter,
+                    injector: &injector,
+                    stealers: &stealers,
+                    pending: &pending,
+                };
+                let expand = &expand;
+                scope.spawn(move || shared.work(local, expand));
+            }
+        });
+        splitter.done()
+    }
+}
+
+/// Passed to the `expand` function of `ParallelTreeBuilder::build`, to create children.
+pub struct Context<'c, 'a: 'c, T: 'a + Send + Sync> {
+    splitter: &'c SyncSplitter<'a, T>,
+    local: &'c Worker<usize>,
+    pending: &'c AtomicUsize,
+}
+
+impl<'c, 'a: 'c, T: 'a + Send + Sync> Context<'c, 'a, T> {
+    /// Stores `children` consecutively in the arena and queues them to be expanded.
+    ///
+    /// Returns the index of the first child in the arena.
+    ///
+    /// Returns `None` if not enough elements were left in the arena. The children aren't stored
+    /// in that case.
+    ///
+    /// Only the children the iterator actually yields are queued, even if its `len` was wrong:
+    /// slots popped for missing children keep their previous contents.
+    pub fn spawn_children<I>(&mut self, children: I) -> Option<usize>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let children = children.into_iter();
+        let (slots, first_index) = self.splitter.pop_n(children.len())?;
+        let mut spawned = 0;
+        for ((slot, child), index) in slots.iter_mut().zip(children).zip(first_index..) {
+            *slot = child;
+            self.local.push(index);
+            spawned += 1;
+        }
+        // Children may already be expanded by other threads, but this node is still pending until
+        // `expand` returns, so the count can't reach zero in the meantime.
+        self.pending.fetch_add(spawned, Ordering::AcqRel);
+        Some(first_index)
+    }
+}
+
+/// The state shared by all the threads of a build.
+struct Shared<'s, 'a: 's, T: 'a + Sync> {
+    splitter: &'s SyncSplitter<'a, T>,
+    injector: &'s Injector<usize>,
+    stealers: &'s [Stealer<usize>],
+    pending: &'s AtomicUsize,
+}
+
+impl<'s, 'a: 's, T: 'a + Send + Sync> Shared<'s, 'a, T> {
+    fn work<E>(&self, local: Worker<usize>, expand: &E)
+    where
+        E: Fn(&mut T, &mut Context<T>),
+    {
+        loop {
+            match self.find_node(&local) {
+                Some(index) => {
+                    let _expanded = Expanded(self.pending);
+                    // Every popped index is queued exactly once, so this is the only reference to
+                    // the node.
+                    let node = unsafe { &mut *self.splitter.data.add(index) };
+                    expand(
+                        node,
+                        &mut Context {
+                            splitter: self.splitter,
+                            local: &local,
+                            pending: self.pending,
+                        },
+                    );
+                }
+                None if self.pending.load(Ordering::Acquire) == 0 => return,
+                None => thread::yield_now(),
+            }
+        }
+    }
+
+    fn find_node(&self, local: &Worker<usize>) -> Option<usize> {
+        local.pop().or_else(|| {
+            iter::repeat_with(|| {
+                self.injector
+                    .steal_batch_and_pop(local)
+                    .or_else(|| self.stealers.iter().map(Stealer::steal).collect())
+            })
+            .find(|steal| !steal.is_retry())
+            .and_then(|steal| steal.success())
+        })
+    }
+}
+
+/// Marks a node as expanded when dropped, even if `expand` panicked, so the other threads don't
+/// wait for it forever.
+struct Expanded<'s>(&'s AtomicUsize);
+
+impl<'s> Drop for Expanded<'s> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParallelTreeBuilder;
+
+    #[derive(Copy, Clone, Default, Debug, PartialEq)]
+    struct Node {
+        depth: u32,
+        first_child: usize,
+        num_children: usize,
+    }
+
+    fn build(num_threads: usize, arena: &mut [Node]) -> usize {
+        ParallelTreeBuilder::new(num_threads).build(arena, Node::default(), |node, context| {
+            // Nodes at depth `d` have `3 - d` children.
+            if node.depth < 3 {
+                let child = Node {
+                    depth: node.depth + 1,
+                    ..Node::default()
+                };
+                node.num_children = 3 - node.depth as usize;
+                node.first_child = context
+                    .spawn_children((0..node.num_children).map(|_| child))
+                    .unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn expands_every_node_once() {
+        for &num_threads in &[0, 1, 4] {
+            let mut arena = vec![Node::default(); 20];
+            // 1 + 3 + 3 * 2 + 3 * 2 * 1
+            assert_eq!(build(num_threads, &mut arena), 16);
+
+            let mut reachable = [false; 16];
+            let mut stack = vec![0];
+            while let Some(index) = stack.pop() {
+                assert!(!reachable[index]);
+                reachable[index] = true;
+                let node = arena[index];
+                let children = node.first_child..node.first_child + node.num_children;
+                assert!(arena[children.clone()].iter().all(|child| child.depth == node.depth + 1));
+                stack.extend(children);
+            }
+            assert!(reachable.iter().all(|&reachable| reachable));
+        }
+    }
+
+    /// Yields `0..len`, but claims to be one element longer.
+    struct Overestimated(::std::ops::Range<usize>);
+
+    impl Iterator for Overestimated {
+        type Item = usize;
+
+        fn next(&mut self) -> Option<usize> {
+            self.0.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.0.len() + 1, Some(self.0.len() + 1))
+        }
+    }
+
+    impl ExactSizeIterator for Overestimated {}
+
+    #[test]
+    fn wrong_length_hints_dont_hang() {
+        let mut arena = vec![Node::default(); 10];
+        let builder = ParallelTreeBuilder::new(2);
+        let num_nodes = builder.build(&mut arena, Node::default(), |node, context| {
+            if node.depth == 0 {
+                let children = Overestimated(0..2).map(|_| Node {
+                    depth: 1,
+                    ..Node::default()
+                });
+                node.num_children = 2;
+                node.first_child = context.spawn_children(children).unwrap();
+            }
+        });
+        // The slot popped for the missing child is left as it was.
+        assert_eq!(num_nodes, 4);
+        assert!(arena[1..3].iter().all(|child| child.depth == 1));
+        assert_eq!(arena[3].depth, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn propagates_panics_from_expand() {
+        let mut arena = vec![Node::default(); 10];
+        build(2, &mut arena);
+    }
+}
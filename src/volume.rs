@@ -0,0 +1,251 @@
+//! Splitting three dimensional buffers (like voxel volumes) into bricks.
+
+use core::marker::PhantomData;
+use core::slice;
+
+use cursor::Cursor;
+
+/// A `VolumeSplitter` allows multiple threads to claim non-overlapping, axis-aligned bricks of a
+/// three dimensional buffer at the same time.
+///
+/// The buffer holds `dims[0] * dims[1] * dims[2]` elements, with `x` varying fastest and `z`
+/// slowest. Bricks are the cells of a grid of fixed-size bricks, handed out in grid order (again
+/// with `x` fastest), and bricks at the far edges of the volume are clipped to it.
+pub struct VolumeSplitter<'a, T: 'a + Sync> {
+    data: *mut T,
+    dims: [usize; 3],
+    brick: [usize; 3],
+    grid: [usize; 3],
+    /// The number of bricks popped so far.
+    next: Cursor,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: 'a + Sync> VolumeSplitter<'a, T> {
+    /// Creates a new `VolumeSplitter` over a volume of the given dimensions, stored in `buffer`,
+    /// which hands out bricks of size `brick`.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `buffer` is shorter than `dims[0] * dims[1] * dims[2]`, or if any dimension of `brick`
+    /// is zero.
+    pub fn new(buffer: &'a mut [T], dims: [usize; 3], brick: [usize; 3]) -> Self {
+        let len = dims[0].checked_mul(dims[1]).and_then(|len| len.checked_mul(dims[2]));
+        assert!(
+            len.is_some_and(|len| len <= buffer.len()),
+            "volume of {:?} elements doesn't fit in {}",
+            dims,
+            buffer.len()
+        );
+        assert!(brick.iter().all(|&len| len > 0), "empty brick size {:?}", brick);
+        VolumeSplitter {
+            data: buffer.as_mut_ptr(),
+            dims,
+            brick,
+            grid: [0, 1, 2].map(|axis| dims[axis].div_ceil(brick[axis])),
+            next: Cursor::new(0),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Returns the dimensions of the volume, in elements.
+    #[inline]
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    /// Returns the size of a brick which isn't clipped by the volume's edges.
+    #[inline]
+    pub fn brick_size(&self) -> [usize; 3] {
+        self.brick
+    }
+
+    /// Returns the number of bricks along each dimension.
+    #[inline]
+    pub fn grid(&self) -> [usize; 3] {
+        self.grid
+    }
+
+    /// Pops the next brick and returns a mutable view over it.
+    ///
+    /// Also returns the brick's coordinates in the grid of bricks; its first element is at those
+    /// coordinates times the brick size.
+    ///
+    /// Returns `None` if every brick was popped already.
+    pub fn pop_brick(&self) -> Option<(Brick<'_, T>, [usize; 3])> {
+        let grid = self.grid;
+        let index = self.next.bump(grid[0] * grid[1] * grid[2], 1)?;
+        let cell = [index % grid[0], index / grid[0] % grid[1], index / (grid[0] * grid[1])];
+        let origin = [0, 1, 2].map(|axis| cell[axis] * self.brick[axis]);
+        let start = origin[0] + self.dims[0] * (origin[1] + self.dims[1] * origin[2]);
+        Some((
+            Brick {
+                // Grid cells don't overlap, and every cell is popped once.
+                data: unsafe { self.data.add(start) },
+                origin,
+                size: [0, 1, 2].map(|axis| self.brick[axis].min(self.dims[axis] - origin[axis])),
+                row_pitch: self.dims[0],
+                slice_pitch: self.dims[0] * self.dims[1],
+                dummy: PhantomData,
+            },
+            cell,
+        ))
+    }
+
+    /// Consumes the splitter and returns the total number of popped bricks.
+    #[inline]
+    pub fn done(self) -> usize {
+        self.next.load()
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for VolumeSplitter<'a, T> {}
+unsafe impl<'a, T: Send + Sync> Send for VolumeSplitter<'a, T> {}
+
+/// A mutable view over a brick, popped from a `VolumeSplitter`.
+///
+/// The brick is made of `size()[1] * size()[2]` rows, each of them a contiguous run of `size()[0]`
+/// elements.
+pub struct Brick<'a, T: 'a> {
+    data: *mut T,
+    origin: [usize; 3],
+    size: [usize; 3],
+    row_pitch: usize,
+    slice_pitch: usize,
+    dummy: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: 'a> Brick<'a, T> {
+    /// Returns the coordinates of the brick's first element in the volume.
+    #[inline]
+    pub fn origin(&self) -> [usize; 3] {
+        self.origin
+    }
+
+    /// Returns the dimensions of the brick, which are smaller than the splitter's brick size at the
+    /// far edges of the volume.
+    #[inline]
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    /// Returns the row at `y` and `z`, relative to the brick's origin.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `y >= self.size()[1]` or `z >= self.size()[2]`.
+    #[inline]
+    pub fn row(&self, y: usize, z: usize) -> &[T] {
+        let start = self.row_start(y, z);
+        unsafe { slice::from_raw_parts(self.data.add(start), self.size[0]) }
+    }
+
+    /// Returns the row at `y` and `z`, relative to the brick's origin, mutably.
+    ///
+    /// Panics
+    /// ===
+    ///
+    /// If `y >= self.size()[1]` or `z >= self.size()[2]`.
+    #[inline]
+    pub fn row_mut(&mut self, y: usize, z: usize) -> &mut [T] {
+        let start = self.row_start(y, z);
+        unsafe { slice::from_raw_parts_mut(self.data.add(start), self.size[0]) }
+    }
+
+    /// Returns an iterator over the rows of the brick, mutably, along with their `y` and `z`
+    /// relative to the brick's origin.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut [T])> + '_ {
+        let (data, size) = (self.data, self.size);
+        let (row_pitch, slice_pitch) = (self.row_pitch, self.slice_pitch);
+        (0..size[2])
+            .flat_map(move |z| (0..size[1]).map(move |y| (y, z)))
+            .map(move |(y, z)| unsafe {
+                // Rows don't overlap, since `size[0] <= row_pitch`.
+                let start = y * row_pitch + z * slice_pitch;
+                (y, z, slice::from_raw_parts_mut(data.add(start), size[0]))
+            })
+    }
+
+    #[inline]
+    fn row_start(&self, y: usize, z: usize) -> usize {
+        assert!(
+            y < self.size[1] && z < self.size[2],
+            "row ({}, {}) out of {:?}",
+            y,
+            z,
+            self.size
+        );
+        y * self.row_pitch + z * self.slice_pitch
+    }
+}
+
+unsafe impl<'a, T: Sync> Sync for Brick<'a, T> {}
+unsafe impl<'a, T: Send> Send for Brick<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::VolumeSplitter;
+
+    #[test]
+    fn bricks_cover_the_volume_once() {
+        let dims = [5, 4, 3];
+        let mut buffer = vec![0usize; 5 * 4 * 3 + 1];
+        {
+            let splitter = VolumeSplitter::new(&mut buffer, dims, [2, 2, 2]);
+            assert_eq!((splitter.brick_size(), splitter.grid()), ([2, 2, 2], [3, 2, 2]));
+            ::std::thread::scope(|scope| {
+                for _ in 0..3 {
+                    let splitter = &splitter;
+                    scope.spawn(move || {
+                        while let Some((mut brick, cell)) = splitter.pop_brick() {
+                            assert_eq!(brick.origin(), cell.map(|coordinate| coordinate * 2));
+                            let id = cell[0] + 3 * (cell[1] + 2 * cell[2]) + 1;
+                            for (_, _, row) in brick.iter_mut() {
+                                row.iter_mut().for_each(|element| *element += id);
+                            }
+                        }
+                    });
+                }
+            });
+            assert!(splitter.pop_brick().is_none());
+            assert_eq!(splitter.done(), 3 * 2 * 2);
+        }
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    let id = x / 2 + 3 * (y / 2 + 2 * (z / 2)) + 1;
+                    assert_eq!(buffer[x + dims[0] * (y + dims[1] * z)], id);
+                }
+            }
+        }
+        assert_eq!(buffer[5 * 4 * 3], 0);
+    }
+
+    #[test]
+    fn edge_bricks_are_clipped() {
+        let mut buffer = [0u8; 27];
+        let splitter = VolumeSplitter::new(&mut buffer, [3, 3, 3], [2, 3, 2]);
+        let (first, _) = splitter.pop_brick().unwrap();
+        let (mut second, cell) = splitter.pop_brick().unwrap();
+        assert_eq!((first.size(), cell, second.size()), ([2, 3, 2], [1, 0, 0], [1, 3, 2]));
+        second.row_mut(2, 1)[0] = 7;
+        assert_eq!(second.row(2, 1), [7]);
+        assert_eq!(second.origin(), [2, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn small_buffers_panic() {
+        let mut buffer = [0u8; 7];
+        VolumeSplitter::new(&mut buffer, [2, 2, 2], [1, 1, 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_bricks_panic() {
+        let mut buffer = [0u8; 8];
+        VolumeSplitter::new(&mut buffer, [2, 2, 2], [1, 0, 1]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Parking threads (or, with the `async` feature, tasks) until a splitter frees up space.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+use core::task::Waker;
+use std::mem;
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use cursor::Cursor;
+
+/// The threads and tasks parked on a splitter, woken whenever it frees up space or is closed.
+///
+/// Notifying is a fence and a load unless something is actually parked, so it's cheap enough to do
+/// on every release.
+pub(crate) struct Waiters {
+    /// The number of parked threads plus the number of registered wakers.
+    waiting: Cursor,
+    wakers: Mutex<Vec<Waker>>,
+    wakeup: Condvar,
+}
+
+impl Waiters {
+    pub fn new() -> Self {
+        Waiters {
+            waiting: Cursor::new(0),
+            wakers: Mutex::new(Vec::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Calls `attempt` until it returns `Some`, parking the thread in between until `notify` is
+    /// called.
+    pub fn wait_for<R, F: FnMut() -> Option<R>>(&self, mut attempt: F) -> R {
+        if let Some(result) = attempt() {
+            return result;
+        }
+        let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        self.waiting.update(|waiting| Some(((), waiting + 1)));
+        // Pairs with the fence in `notify`: either the attempt below sees the freed space, or the
+        // notifier sees this thread waiting (and takes the lock, so it can't notify too early).
+        fence(Ordering::SeqCst);
+        let result = loop {
+            if let Some(result) = attempt() {
+                break result;
+            }
+            wakers = self.wakeup.wait(wakers).unwrap_or_else(PoisonError::into_inner);
+        };
+        self.waiting.update(|waiting| Some(((), waiting - 1)));
+        result
+    }
+
+    /// Calls `attempt`, registering the task to be woken by `notify` if it returns `None`.
+    #[cfg(feature = "async")]
+    pub fn poll_for<R, F>(&self, context: &Context, mut attempt: F) -> Poll<R>
+    where
+        F: FnMut() -> Option<R>,
+    {
+        if let Some(result) = attempt() {
+            return Poll::Ready(result);
+        }
+        {
+            let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+            if !wakers.iter().any(|waker| waker.will_wake(context.waker())) {
+                wakers.push(context.waker().clone());
+                self.waiting.update(|waiting| Some(((), waiting + 1)));
+            }
+        }
+        // As in `wait_for`. If this attempt succeeds, the registered waker just wakes spuriously.
+        fence(Ordering::SeqCst);
+        match attempt() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Wakes every parked thread and task, to make another attempt. Call this after freeing up
+    /// space.
+    #[inline]
+    pub fn notify(&self) {
+        fence(Ordering::SeqCst);
+        if self.waiting.load() != 0 {
+            self.notify_slow();
+        }
+    }
+
+    #[cold]
+    fn notify_slow(&self) {
+        let wakers = {
+            let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+            let len = wakers.len();
+            self.waiting.update(|waiting| Some(((), waiting - len)));
+            mem::take(&mut *wakers)
+        };
+        self.wakeup.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,118 @@
+//! Single-attempt pops, for callers which would rather do other work than retry under contention.
+
+use core::error::Error;
+use core::fmt;
+use core::slice;
+
+use SyncSplitter;
+
+#[allow(clippy::mut_from_ref)]
+impl<'a, T: 'a + Sync> SyncSplitter<'a, T> {
+    /// Like `pop`, but gives up instead of retrying if another thread popped concurrently.
+    ///
+    /// The single attempt may also fail spuriously, so `TryPopError::Contended` doesn't guarantee
+    /// that any other thread made progress. Unlike `TryPopError::Exhausted`, it's worth retrying
+    /// later.
+    #[inline]
+    pub fn try_pop_weak(&self) -> Result<(&mut T, usize), TryPopError> {
+        self.bump_weak(1).map(|index| {
+            (unsafe { &mut *self.data.add(index) }, self.offset + index)
+        })
+    }
+
+    /// Like `pop_n`, but gives up instead of retrying if another thread popped concurrently. See
+    /// `try_pop_weak`.
+    #[inline]
+    pub fn try_pop_n_weak(&self, len: usize) -> Result<(&mut [T], usize), TryPopError> {
+        self.bump_weak(len).map(|index| {
+            (
+                unsafe { slice::from_raw_parts_mut(self.data.add(index), len) },
+                self.offset + index,
+            )
+        })
+    }
+
+    #[inline]
+    fn bump_weak(&self, len: usize) -> Result<usize, TryPopError> {
+        #[cfg(feature = "stats")]
+        self.stats.record(len);
+        let result = self.next.bump_weak(self.len, len);
+        match result {
+            Ok(index) => self.advanced(index, index + len),
+            Err(TryPopError::Exhausted) => self.exhausted(len),
+            Err(TryPopError::Contended) => {}
+        }
+        result
+    }
+}
+
+/// The error returned by `SyncSplitter::try_pop_weak` and `SyncSplitter::try_pop_n_weak`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TryPopError {
+    /// Not enough elements were left in the underlying slice.
+    Exhausted,
+    /// The attempt failed, either because another thread popped concurrently or spuriously.
+    Contended,
+}
+
+impl fmt::Display for TryPopError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match *self {
+            TryPopError::Exhausted => "not enough elements left in slice",
+            TryPopError::Contended => "pop attempt was contended",
+        })
+    }
+}
+
+impl Error for TryPopError {}
+
+#[cfg(test)]
+mod tests {
+    use super::TryPopError;
+    use SyncSplitter;
+
+    #[test]
+    fn try_pop_weak_distinguishes_exhaustion() {
+        let mut buffer = [1u32, 2, 3];
+        let splitter = SyncSplitter::new(&mut buffer);
+        let mut popped = Vec::new();
+        loop {
+            match splitter.try_pop_weak() {
+                Ok((&mut value, index)) => popped.push((value, index)),
+                Err(TryPopError::Contended) => {}
+                Err(TryPopError::Exhausted) => break,
+            }
+        }
+        assert_eq!(popped, [(1, 0), (2, 1), (3, 2)]);
+        assert_eq!(splitter.try_pop_n_weak(1), Err(TryPopError::Exhausted));
+        loop {
+            match splitter.try_pop_n_weak(0) {
+                Err(TryPopError::Contended) => continue,
+                result => break assert_eq!(result, Ok((&mut [][..], 3))),
+            }
+        }
+        assert_eq!(splitter.done(), 3);
+    }
+
+    #[test]
+    fn try_pop_weak_from_many_threads_pops_everything_once() {
+        let mut buffer = vec![0usize; 10_000];
+        {
+            let splitter = SyncSplitter::new(&mut buffer);
+            ::std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    let splitter = &splitter;
+                    scope.spawn(move || loop {
+                        match splitter.try_pop_weak() {
+                            Ok((slot, index)) => *slot += index + 1,
+                            Err(TryPopError::Contended) => {}
+                            Err(TryPopError::Exhausted) => break,
+                        }
+                    });
+                }
+            });
+            assert_eq!(splitter.done(), 10_000);
+        }
+        assert!(buffer.iter().enumerate().all(|(index, &value)| value == index + 1));
+    }
+}
@@ -0,0 +1,68 @@
+//! Link-time check that the hot pop paths can't panic.
+//!
+//! Each wrapper below holds a guard whose destructor calls a function which doesn't exist. If the
+//! wrapped call could unwind, the destructor would have to run during unwinding and the test
+//! binary would fail to link. This is the technique of the `no_panic` crate, applied here to the
+//! pops as instantiated by a caller, so the crate itself doesn't need a proc-macro dependency (or
+//! attributes on its generic functions) for a check which only runs in CI.
+//!
+//! Optimizations are needed to remove the panic paths which are provably unreachable, so run with:
+//!
+//! ```text
+//! cargo test --release --features check-no-panic --test no_panic
+//! ```
+//!
+//! and again with each feature which adds code to pops (e.g.
+//! `--features check-no-panic,indicatif`).
+#![cfg(all(feature = "check-no-panic", not(debug_assertions)))]
+
+extern crate sync_splitter;
+
+use std::mem;
+use sync_splitter::SyncSplitter;
+
+struct MustNotPanic;
+
+extern "C" {
+    #[link_name = "\n\nERROR: a sync_splitter pop function may panic\n\n"]
+    fn pop_may_panic() -> !;
+}
+
+impl Drop for MustNotPanic {
+    fn drop(&mut self) {
+        unsafe { pop_may_panic() }
+    }
+}
+
+#[inline(never)]
+fn no_panic<R, F: FnOnce() -> R>(f: F) -> R {
+    let guard = MustNotPanic;
+    let result = f();
+    mem::forget(guard);
+    result
+}
+
+#[inline(never)]
+fn pop<'s>(splitter: &'s SyncSplitter<u64>) -> Option<(&'s mut u64, usize)> {
+    no_panic(|| splitter.pop())
+}
+
+#[inline(never)]
+fn pop_two<'s>(splitter: &'s SyncSplitter<u64>) -> Option<((&'s mut u64, &'s mut u64), usize)> {
+    no_panic(|| splitter.pop_two())
+}
+
+#[inline(never)]
+fn pop_n<'s>(splitter: &'s SyncSplitter<u64>, len: usize) -> Option<(&'s mut [u64], usize)> {
+    no_panic(|| splitter.pop_n(len))
+}
+
+#[test]
+fn pops_do_not_panic() {
+    let mut buffer = [0u64; 8];
+    let splitter = SyncSplitter::new(&mut buffer);
+    assert!(pop(&splitter).is_some());
+    assert!(pop_two(&splitter).is_some());
+    assert!(pop_n(&splitter, 5).is_some());
+    assert!(pop_n(&splitter, 1).is_none());
+}
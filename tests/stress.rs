@@ -0,0 +1,282 @@
+//! Multi-threaded stress tests for `SyncSplitter`.
+//!
+//! Each workload runs rounds in which a number of threads pop from a shared splitter, with pop
+//! sizes drawn from a seeded distribution, until the slice is exhausted. After every round, the
+//! granted ranges must be disjoint and exactly cover the prefix reported by `done()`, and every
+//! element must hold what its owner wrote to it.
+//!
+//! The same workloads also run against `MutexSplitter`, which is trivially correct: with a single
+//! thread, where the outcome doesn't depend on scheduling, both must grant exactly the same ranges.
+//!
+//! The defaults are quick enough for `cargo test`. For longer runs, override them with the
+//! `STRESS_SEED`, `STRESS_THREADS` and `STRESS_MILLIS` environment variables, e.g.:
+//!
+//! ```text
+//! STRESS_MILLIS=60000 STRESS_THREADS=32 cargo test --release --test stress
+//! ```
+
+extern crate sync_splitter;
+
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "std")]
+use sync_splitter::MutexSplitter;
+use sync_splitter::{Splittable, SyncSplitter, TryPopError};
+
+/// A xorshift generator, so that a workload's pop sizes only depend on its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift's state must never be zero.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum PopSizes {
+    /// Always the same size.
+    Fixed(usize),
+    /// Uniform in `min..=max`.
+    Uniform(usize, usize),
+    /// A power of two, up to `2^max_log2`, with smaller ones more likely.
+    PowersOfTwo(u32),
+}
+
+impl PopSizes {
+    fn sample(self, rng: &mut Rng) -> usize {
+        match self {
+            PopSizes::Fixed(len) => len,
+            PopSizes::Uniform(min, max) => min + rng.below(max - min + 1),
+            PopSizes::PowersOfTwo(max_log2) => 1 << rng.next().trailing_zeros().min(max_log2),
+        }
+    }
+}
+
+/// Which splitter a workload runs against.
+#[derive(Copy, Clone, Debug)]
+enum Implementation {
+    Sync,
+    #[cfg(feature = "std")]
+    Mutex,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Workload {
+    implementation: Implementation,
+    threads: usize,
+    arena_len: usize,
+    sizes: PopSizes,
+    duration: Duration,
+    seed: u64,
+}
+
+impl Workload {
+    fn new(sizes: PopSizes) -> Self {
+        let var = |name: &str| env::var(name).ok().and_then(|value| value.parse().ok());
+        Workload {
+            implementation: Implementation::Sync,
+            threads: var("STRESS_THREADS").unwrap_or(8) as usize,
+            arena_len: 1 << 16,
+            sizes,
+            duration: Duration::from_millis(var("STRESS_MILLIS").unwrap_or(200)),
+            seed: var("STRESS_SEED").unwrap_or(0x5EED),
+        }
+    }
+
+    /// Runs rounds until the duration elapsed (but at least one), returning how many ran.
+    fn run(self) -> usize {
+        let start = Instant::now();
+        let mut arena = vec![(0usize, 0usize); self.arena_len];
+        let mut rounds = 0;
+        while rounds == 0 || start.elapsed() < self.duration {
+            self.round(&mut arena, rounds as u64);
+            rounds += 1;
+        }
+        rounds
+    }
+
+    fn round(&self, arena: &mut [(usize, usize)], round: u64) -> Vec<Grant> {
+        let (done, granted) = match self.implementation {
+            Implementation::Sync => self.pop_all(SyncSplitter::new(arena), round),
+            #[cfg(feature = "std")]
+            Implementation::Mutex => self.pop_all(MutexSplitter::new(arena), round),
+        };
+        verify(self, arena, done, &mut granted.clone());
+        granted
+    }
+
+    /// Has every thread hammer `splitter` until it runs out, returning `done()` and the grants.
+    fn pop_all<S: Hammered>(&self, splitter: S, round: u64) -> (usize, Vec<Grant>) {
+        let granted = thread::scope(|scope| {
+            let threads = (0..self.threads)
+                .map(|thread| {
+                    let splitter = &splitter;
+                    let mut rng = Rng::new(self.seed ^ (round << 32) ^ thread as u64);
+                    let sizes = self.sizes;
+                    scope.spawn(move || hammer(splitter, thread, &mut rng, sizes))
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        (splitter.done(), granted)
+    }
+}
+
+/// A range granted to `thread`, at position `sequence` among its pops.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Grant {
+    thread: usize,
+    sequence: usize,
+    start: usize,
+    len: usize,
+}
+
+/// What `hammer` writes to every element: its thread and the position of the pop among the
+/// thread's pops.
+type Element = (usize, usize);
+
+/// The pops `hammer` uses: those of `Splittable`, plus single-attempt ones where there are any.
+#[allow(clippy::mut_from_ref)]
+trait Hammered: Splittable<Element> + Sync {
+    fn try_pop_n_weak(&self, len: usize) -> Result<(&mut [Element], usize), TryPopError>;
+}
+
+impl<'a> Hammered for SyncSplitter<'a, Element> {
+    fn try_pop_n_weak(&self, len: usize) -> Result<(&mut [Element], usize), TryPopError> {
+        SyncSplitter::try_pop_n_weak(self, len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Hammered for MutexSplitter<'a, Element> {
+    fn try_pop_n_weak(&self, len: usize) -> Result<(&mut [Element], usize), TryPopError> {
+        self.pop_n(len).ok_or(TryPopError::Exhausted)
+    }
+}
+
+/// Pops with every kind of pop until the splitter runs out, marking every popped element.
+fn hammer<S: Hammered>(
+    splitter: &S,
+    thread: usize,
+    rng: &mut Rng,
+    sizes: PopSizes,
+) -> Vec<Grant> {
+    let mut granted = Vec::new();
+    let mut failures = 0;
+    while failures < 8 {
+        let popped = match rng.below(4) {
+            0 => splitter.pop().map(|(element, start)| (vec![element], start)),
+            1 => splitter
+                .pop_two()
+                .map(|((first, second), start)| (vec![first, second], start)),
+            2 => splitter
+                .pop_n(sizes.sample(rng))
+                .map(|(slice, start)| (slice.iter_mut().collect(), start)),
+            _ => loop {
+                match splitter.try_pop_n_weak(sizes.sample(rng)) {
+                    Ok((slice, start)) => break Some((slice.iter_mut().collect(), start)),
+                    Err(TryPopError::Contended) => continue,
+                    Err(TryPopError::Exhausted) => break None,
+                }
+            },
+        };
+        // Smaller pops may still fit after a larger one failed, so only give up after a few.
+        let (elements, start): (Vec<&mut (usize, usize)>, usize) = match popped {
+            Some(popped) => popped,
+            None => {
+                failures += 1;
+                continue;
+            }
+        };
+        let sequence = granted.len();
+        let len = elements.len();
+        for element in elements {
+            *element = (thread, sequence);
+        }
+        granted.push(Grant {
+            thread,
+            sequence,
+            start,
+            len,
+        });
+    }
+    granted
+}
+
+fn verify(workload: &Workload, arena: &[(usize, usize)], done: usize, granted: &mut [Grant]) {
+    assert!(done <= workload.arena_len, "{:?}", workload);
+    // Zero-length grants may share their start with another grant, so they must come first.
+    granted.sort_by_key(|grant| (grant.start, grant.len));
+    let mut next = 0;
+    for grant in granted.iter() {
+        assert_eq!(grant.start, next, "gap or overlap at {:?} in {:?}", grant, workload);
+        next += grant.len;
+        for element in &arena[grant.start..grant.start + grant.len] {
+            assert_eq!(*element, (grant.thread, grant.sequence), "{:?}", workload);
+        }
+    }
+    assert_eq!(next, done, "{:?}", workload);
+}
+
+#[test]
+fn single_elements() {
+    assert!(Workload::new(PopSizes::Fixed(1)).run() > 0);
+}
+
+#[test]
+fn uniform_small_pops() {
+    assert!(Workload::new(PopSizes::Uniform(0, 16)).run() > 0);
+}
+
+#[test]
+fn mixed_power_of_two_pops() {
+    assert!(Workload::new(PopSizes::PowersOfTwo(10)).run() > 0);
+}
+
+#[test]
+fn more_threads_than_elements() {
+    let mut workload = Workload::new(PopSizes::Uniform(1, 3));
+    workload.arena_len = 5;
+    workload.threads = workload.threads.max(16);
+    assert!(workload.run() > 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn mutex_reference() {
+    let mut workload = Workload::new(PopSizes::Uniform(0, 16));
+    workload.implementation = Implementation::Mutex;
+    assert!(workload.run() > 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn single_thread_matches_mutex_reference() {
+    for &sizes in &[PopSizes::Fixed(3), PopSizes::Uniform(0, 16), PopSizes::PowersOfTwo(10)] {
+        let mut workload = Workload::new(sizes);
+        workload.threads = 1;
+        let mut arena = vec![(0, 0); workload.arena_len];
+        let granted = workload.round(&mut arena, 0);
+
+        workload.implementation = Implementation::Mutex;
+        let mut reference = vec![(0, 0); workload.arena_len];
+        assert_eq!(workload.round(&mut reference, 0), granted, "{:?}", workload);
+        assert!(arena == reference, "{:?}", workload);
+    }
+}